@@ -1,3 +1,4 @@
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Debug, Error, Clone)]
@@ -8,8 +9,21 @@ pub enum Error {
     Repl(String),
     #[error("[line {0}] Error {1}: {2}")]
     SyntaxError(String, String, String),
+    #[error("[line {0}] Error at end: {1}")]
+    UnexpectedEof(String, String),
+    /// Line, kind and message of an error raised by the interpreter.
+    #[error("[line {0}] Runtime error: {2}")]
+    RuntimeError(String, ErrorKind, String),
+    /// A `throw` that no `catch` caught, with the thrown value as text.
+    #[error("[line {0}] Uncaught exception: {1}")]
+    Thrown(String, String),
+    /// An import of a module that can't be found or parsed, or that closes a cycle.
+    #[error("[line {0}] Import error: {1}")]
+    ImportError(String, String),
     #[error("Parsing error: {0}")]
     ParsingError(String),
+    #[error("Formatting error: {0}")]
+    FormatError(String),
     #[error("Anyhow: {0}")]
     Anyhow(String),
 }
@@ -19,3 +33,39 @@ impl std::convert::From<anyhow::Error> for Error {
         Error::Anyhow(e.to_string())
     }
 }
+
+/// What went wrong in a runtime error. A `catch` sees it as the `kind` of the error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A value of the wrong type, e.g. adding a number to a string.
+    Type,
+    /// A variable that isn't defined.
+    Name,
+    /// An index past the end of a list or tuple.
+    Index,
+    /// A key that isn't in a map.
+    Key,
+    ZeroDivision,
+    /// Calls nested deeper than the interpreter allows.
+    Recursion,
+    /// A match without an arm for the value.
+    Match,
+    /// A value of the right type that can't be used, e.g. a tuple of the wrong length.
+    Value,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Type => "TypeError",
+            ErrorKind::Name => "NameError",
+            ErrorKind::Index => "IndexError",
+            ErrorKind::Key => "KeyError",
+            ErrorKind::ZeroDivision => "ZeroDivisionError",
+            ErrorKind::Recursion => "RecursionError",
+            ErrorKind::Match => "MatchError",
+            ErrorKind::Value => "ValueError",
+        };
+        f.write_str(name)
+    }
+}
@@ -0,0 +1,600 @@
+use crate::parser::{Ast, Parser};
+use crate::{Error, Scanner, Token, TokenType, TriviaKind};
+
+const INDENT: &str = "    ";
+const MAX_WIDTH: usize = 100;
+
+/// Formats Seid source in the canonical style.
+///
+/// The formatter works on the lossless token stream and only decides on the whitespace
+/// between tokens, so comments are kept and the program itself can't change. The result
+/// is checked for exactly that before it is returned. Formatting formatted source is a
+/// no-op.
+pub fn format_source(source: &str) -> Result<String, Error> {
+    // Refuse to touch code that doesn't parse
+    let mut ast = Ast::new();
+    Parser::new(Scanner::new(source), &mut ast)?.parse()?;
+
+    let tokens = Scanner::lossless(source).scan_tokens()?;
+    let mut formatter = Formatter::new(&tokens);
+    formatter.format();
+    let formatted = formatter.out;
+
+    if significant_text(source)? != significant_text(&formatted)? {
+        return Err(Error::FormatError(String::from(
+            "formatting would change the program, leaving it as is",
+        )));
+    }
+    Ok(formatted)
+}
+
+/// Every token and comment of the source, without the whitespace around them.
+fn significant_text(source: &str) -> Result<Vec<String>, Error> {
+    let mut text = Vec::new();
+    for token in Scanner::lossless(source) {
+        let token = token?;
+        let trivia = token.leading_trivia.iter().chain(&token.trailing_trivia);
+        for trivia in trivia {
+            if matches!(
+                trivia.kind,
+                TriviaKind::LineComment | TriviaKind::BlockComment
+            ) {
+                text.push(trivia.text.trim_end().to_string());
+            }
+        }
+        text.push(token.lexeme.trim_end().to_string());
+    }
+    Ok(text)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Colon {
+    /// `a ? b : c`
+    Conditional,
+    /// `lambda x: x`
+    Lambda,
+    /// `list[a:b]`
+    Slice,
+    /// `{key: value}`
+    Entry,
+    /// `outer: while (...)`
+    Label,
+}
+
+struct Formatter<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    // Index of the matching closing parenthesis, bracket or map brace for every opening one
+    matching: Vec<Option<usize>>,
+    // Whether a brace belongs to a map rather than a block
+    maps: Vec<bool>,
+    // Whether a brace holds the arms of a match, which always go one per line
+    arms: Vec<bool>,
+    // What every `:` belongs to, which decides the spaces around it
+    colons: Vec<Option<Colon>>,
+    out: String,
+    indent: usize,
+    at_line_start: bool,
+    in_statement: bool,
+    // Set when a comment forced a line break in the middle of a statement
+    continuation: bool,
+    paren_depth: usize,
+    // For every open parenthesis, bracket or map, whether its contents are wrapped one item
+    // per line
+    wrapped: Vec<bool>,
+}
+
+impl<'t, 'a> Formatter<'t, 'a> {
+    fn new(tokens: &'t [Token<'a>]) -> Self {
+        let mut matching = vec![None; tokens.len()];
+        let mut maps = vec![false; tokens.len()];
+        let mut arms = vec![false; tokens.len()];
+        let mut colons = vec![None; tokens.len()];
+        // Index of the opening token for every closing one
+        let mut openers = vec![None; tokens.len()];
+        let mut open = Vec::new();
+        // The `?` and `lambda` still waiting for their `:`, per nesting level
+        let mut pending = vec![Vec::new()];
+        for (i, token) in tokens.iter().enumerate() {
+            match token.token_type {
+                TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => {
+                    // Like in the parser, a `{` where a statement can start is a block
+                    let starts_statement = i == 0
+                        || match tokens[i - 1].token_type {
+                            TokenType::LeftBrace | TokenType::RightBrace => {
+                                !maps[i - 1] && !arms[i - 1]
+                            }
+                            token_type => matches!(
+                                token_type,
+                                TokenType::RightParen
+                                    | TokenType::Else
+                                    | TokenType::Try
+                                    | TokenType::Finally
+                                    | TokenType::Semicolon
+                                    | TokenType::DocComment
+                            ),
+                        };
+                    // `match (value) {`
+                    let follows_match = i > 1
+                        && openers[i - 1].is_some_and(|start: usize| {
+                            start > 0 && tokens[start - 1].token_type == TokenType::Match
+                        });
+                    arms[i] = token.token_type == TokenType::LeftBrace && follows_match;
+                    maps[i] =
+                        token.token_type == TokenType::LeftBrace && !starts_statement && !arms[i];
+                    open.push(i);
+                    pending.push(Vec::new());
+                }
+                TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => {
+                    if let Some(start) = open.pop() {
+                        maps[i] = maps[start];
+                        arms[i] = arms[start];
+                        openers[i] = Some(start);
+                        if token.token_type != TokenType::RightBrace || maps[start] || arms[start] {
+                            matching[start] = Some(i);
+                        }
+                    }
+                    if pending.len() > 1 {
+                        pending.pop();
+                    }
+                }
+                TokenType::Question => pending.last_mut().unwrap().push(Colon::Conditional),
+                TokenType::Lambda => pending.last_mut().unwrap().push(Colon::Lambda),
+                TokenType::Colon
+                    if matches!(
+                        tokens.get(i + 1).map(|token| token.token_type),
+                        Some(TokenType::While | TokenType::For)
+                    ) =>
+                {
+                    colons[i] = Some(Colon::Label)
+                }
+                TokenType::Colon => {
+                    let in_map = open.last().is_some_and(|start| maps[*start]);
+                    let colon = pending.last_mut().unwrap().pop();
+                    colons[i] =
+                        Some(colon.unwrap_or(if in_map { Colon::Entry } else { Colon::Slice }));
+                }
+                _ => {}
+            }
+        }
+        Formatter {
+            tokens,
+            matching,
+            maps,
+            arms,
+            colons,
+            out: String::new(),
+            indent: 0,
+            at_line_start: true,
+            in_statement: false,
+            continuation: false,
+            paren_depth: 0,
+            wrapped: Vec::new(),
+        }
+    }
+
+    fn format(&mut self) {
+        for i in 0..self.tokens.len() {
+            self.leading_trivia(i);
+            if self.tokens[i].token_type == TokenType::Eof {
+                break;
+            }
+            self.token(i);
+        }
+        self.newline();
+        if self.out.trim().is_empty() {
+            self.out.clear();
+        }
+    }
+
+    fn token(&mut self, i: usize) {
+        let token = &self.tokens[i];
+        let next = self.tokens.get(i + 1).map(|t| t.token_type);
+        match token.token_type {
+            TokenType::RightBrace if self.is_block(i) => {
+                self.indent = self.indent.saturating_sub(1);
+                if i == 0 || self.tokens[i - 1].token_type != TokenType::LeftBrace {
+                    self.newline();
+                }
+            }
+            _ if self.closes(i) => {
+                if self.wrapped.pop() == Some(true) {
+                    self.indent = self.indent.saturating_sub(1);
+                    self.newline();
+                }
+                self.paren_depth = self.paren_depth.saturating_sub(1);
+            }
+            TokenType::DocComment => self.newline(),
+            _ => {}
+        }
+        if !self.at_line_start && i > 0 && self.space_between(i - 1, i) {
+            self.space();
+        }
+        self.write(token.lexeme.trim_end());
+        self.in_statement = true;
+        self.trailing_trivia(i);
+
+        match token.token_type {
+            TokenType::LeftBrace if self.is_block(i) => {
+                self.indent += 1;
+                if next != Some(TokenType::RightBrace) {
+                    self.end_statement();
+                }
+            }
+            TokenType::RightBrace if self.is_block(i) => {
+                let continues = matches!(
+                    next,
+                    Some(TokenType::Else)
+                        | Some(TokenType::Catch)
+                        | Some(TokenType::Finally)
+                        | Some(TokenType::Semicolon)
+                        | Some(TokenType::RightParen)
+                        | Some(TokenType::Comma)
+                );
+                if self.paren_depth == 0 && !continues {
+                    self.end_statement();
+                }
+            }
+            _ if self.opens(i) => {
+                self.paren_depth += 1;
+                let wrap = self.should_wrap(i);
+                self.wrapped.push(wrap);
+                if wrap {
+                    self.indent += 1;
+                    self.newline();
+                }
+            }
+            TokenType::Comma if self.wrapped.last() == Some(&true) => self.newline(),
+            TokenType::Semicolon if self.paren_depth == 0 => self.end_statement(),
+            TokenType::DocComment => self.end_statement(),
+            _ => {}
+        }
+    }
+
+    /// Comments before a token sit on their own lines. A blank line before a statement
+    /// or comment is kept, more than one are collapsed.
+    fn leading_trivia(&mut self, i: usize) {
+        let trivia = &self.tokens[i].leading_trivia;
+        let mut newlines = 0;
+        for (n, piece) in trivia.iter().enumerate() {
+            match piece.kind {
+                TriviaKind::Newline => newlines += 1,
+                TriviaKind::Whitespace => {}
+                TriviaKind::LineComment | TriviaKind::BlockComment => {
+                    if newlines > 0 {
+                        self.break_line();
+                    }
+                    if newlines > 1 {
+                        self.blank_line();
+                    }
+                    if !self.at_line_start {
+                        self.space();
+                    }
+                    self.write(piece.text.trim_end());
+                    let own_line = piece.kind == TriviaKind::LineComment
+                        || trivia[n + 1..]
+                            .iter()
+                            .any(|t| t.kind == TriviaKind::Newline);
+                    if own_line {
+                        self.break_line();
+                    } else {
+                        self.space();
+                    }
+                    newlines = 0;
+                }
+            }
+        }
+        if newlines > 1 {
+            self.blank_line();
+        }
+    }
+
+    /// Comments after a token on the same line stay there.
+    fn trailing_trivia(&mut self, i: usize) {
+        for piece in &self.tokens[i].trailing_trivia {
+            match piece.kind {
+                TriviaKind::LineComment => {
+                    self.space();
+                    self.write(piece.text.trim_end());
+                    // A list that goes one item per line breaks there anyway
+                    let ends_item = match self.tokens[i].token_type {
+                        TokenType::Comma => self.wrapped.last() == Some(&true),
+                        _ => self.opens(i),
+                    };
+                    if ends_item {
+                        self.newline();
+                    } else {
+                        self.break_line();
+                    }
+                }
+                TriviaKind::BlockComment => {
+                    self.space();
+                    self.write(piece.text);
+                }
+                TriviaKind::Whitespace | TriviaKind::Newline => {}
+            }
+        }
+    }
+
+    fn space_between(&self, prev: usize, next: usize) -> bool {
+        let (before, after) = (&self.tokens[prev], &self.tokens[next]);
+        match after.token_type {
+            TokenType::Semicolon
+            | TokenType::Comma
+            | TokenType::RightParen
+            | TokenType::RightBracket
+            | TokenType::Dot
+            | TokenType::DotDot
+            | TokenType::DotDotEqual => return false,
+            TokenType::RightBrace if self.maps[next] => return false,
+            TokenType::Colon => return self.colons[next] == Some(Colon::Conditional),
+            TokenType::RightBrace if before.token_type == TokenType::LeftBrace => return false,
+            TokenType::PlusPlus | TokenType::MinusMinus if !self.is_unary(next) => return false,
+            // A call or an index
+            TokenType::LeftParen | TokenType::LeftBracket if self.ends_operand(prev) => {
+                return false
+            }
+            _ => {}
+        }
+        match before.token_type {
+            TokenType::LeftParen
+            | TokenType::LeftBracket
+            | TokenType::Dot
+            | TokenType::DotDot
+            | TokenType::DotDotEqual
+            | TokenType::DotDotDot => false,
+            TokenType::LeftBrace if self.maps[prev] => false,
+            TokenType::Colon => self.colons[prev] != Some(Colon::Slice),
+            // Keep `- -x` and `- --x` apart
+            TokenType::Minus | TokenType::Bang | TokenType::Tilde if self.is_unary(prev) => {
+                before.token_type == TokenType::Minus
+                    && matches!(after.token_type, TokenType::Minus | TokenType::MinusMinus)
+            }
+            TokenType::PlusPlus | TokenType::MinusMinus if self.is_unary(prev) => false,
+            _ => true,
+        }
+    }
+
+    /// An opening parenthesis, bracket, map brace or brace of match arms, the tokens that
+    /// group items.
+    fn opens(&self, i: usize) -> bool {
+        match self.tokens[i].token_type {
+            TokenType::LeftParen | TokenType::LeftBracket => true,
+            TokenType::LeftBrace => self.maps[i] || self.arms[i],
+            _ => false,
+        }
+    }
+
+    fn closes(&self, i: usize) -> bool {
+        match self.tokens[i].token_type {
+            TokenType::RightParen | TokenType::RightBracket => true,
+            TokenType::RightBrace => self.maps[i] || self.arms[i],
+            _ => false,
+        }
+    }
+
+    /// A brace of a block of statements.
+    fn is_block(&self, i: usize) -> bool {
+        !self.maps[i] && !self.arms[i]
+    }
+
+    fn is_unary(&self, i: usize) -> bool {
+        i == 0 || !self.ends_operand(i - 1)
+    }
+
+    fn ends_operand(&self, i: usize) -> bool {
+        match self.tokens[i].token_type {
+            // `x++`, not `++x`
+            TokenType::PlusPlus | TokenType::MinusMinus => !self.is_unary(i),
+            TokenType::RightBrace => self.maps[i] || self.arms[i],
+            token_type => matches!(
+                token_type,
+                TokenType::Number
+                    | TokenType::String
+                    | TokenType::Identifier
+                    | TokenType::True
+                    | TokenType::False
+                    | TokenType::Nil
+                    | TokenType::This
+                    | TokenType::Super
+                    | TokenType::RightParen
+                    | TokenType::RightBracket
+            ),
+        }
+    }
+
+    /// A parenthesized list is put one item per line when it doesn't fit on the line
+    /// or holds comments.
+    fn should_wrap(&self, open: usize) -> bool {
+        let close = match self.matching[open] {
+            Some(close) => close,
+            None => return false,
+        };
+        if self.arms[open] {
+            return close > open + 1;
+        }
+        let has_comment = |token: &Token| {
+            token
+                .leading_trivia
+                .iter()
+                .chain(&token.trailing_trivia)
+                .any(|t| matches!(t.kind, TriviaKind::LineComment | TriviaKind::BlockComment))
+        };
+        if self.tokens[open]
+            .trailing_trivia
+            .iter()
+            .any(|t| t.kind == TriviaKind::LineComment)
+        {
+            return true;
+        }
+        let mut depth = 0;
+        let mut has_comma = false;
+        let mut width = self.out.len() - self.out.rfind('\n').map_or(0, |n| n + 1);
+        for i in open + 1..=close {
+            let token = &self.tokens[i];
+            if self.opens(i) {
+                depth += 1;
+            } else if self.closes(i) {
+                depth -= 1;
+            } else if token.token_type == TokenType::Comma && depth == 0 {
+                has_comma = true;
+            }
+            if has_comment(token) {
+                return true;
+            }
+            width += token.lexeme.len() + usize::from(self.space_between(i - 1, i));
+        }
+        has_comma && width > MAX_WIDTH
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.at_line_start {
+            let depth = self.indent + usize::from(self.continuation);
+            self.out.push_str(&INDENT.repeat(depth));
+            self.at_line_start = false;
+        }
+        self.out.push_str(text);
+    }
+
+    fn space(&mut self) {
+        if !self.at_line_start && !self.out.ends_with(' ') {
+            self.out.push(' ');
+        }
+    }
+
+    fn newline(&mut self) {
+        if !self.at_line_start {
+            self.out.push('\n');
+            self.at_line_start = true;
+        }
+    }
+
+    /// A line break in the middle of a statement indents what follows.
+    fn break_line(&mut self) {
+        if !self.at_line_start {
+            self.newline();
+            self.continuation = self.in_statement;
+        }
+    }
+
+    fn end_statement(&mut self) {
+        self.newline();
+        self.in_statement = false;
+        self.continuation = false;
+    }
+
+    fn blank_line(&mut self) {
+        self.newline();
+        let opens_block = self.out.trim_end().ends_with('{');
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") && !opens_block {
+            self.out.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        format_source(source).unwrap_or_else(|e| panic!("`{}` should format: {:?}", source, e))
+    }
+
+    /// Programs in all kinds of shapes, with comments in all kinds of places.
+    const SOURCES: &[&str] = &[
+        "var   x=1+2*3;print x ;",
+        "fun add(a,b){return a+b;}\nprint add(1, 2);",
+        "if(a){print 1;}else if(b)print 2;else{print 3;}",
+        "outer:while(true){for(var i=0;i<3;i++){if(i==1)continue outer;break outer;}}",
+        "var f = lambda x, y: x ? y : -x;\nvar m = {a: 1, \"b\": [1, 2, ...xs], c};",
+        "print xs[1:], xs[:2], xs[a:b], -(-x), - --x, !!y;",
+        "var r = match (x) { 0 => \"zero\", n if n < 0 => \"negative\", _ => \"positive\" };",
+        "try { throw 1; } catch (e) { print e; } finally { print \"done\"; }",
+        "// A comment on its own line\nvar x = 1; // and one after a statement\n\n\n\nprint x;",
+        "/* block */ var y = /* inside */ 2;\n/* spanning\n   lines */\nprint y;",
+        "var list = [\n    1, // one\n    2,\n];",
+        "print f( // first\n    a, b);",
+        "/// Adds one.\nfun inc(n) {\n    // Nothing fancy\n    return n + 1;\n}",
+        "{\n\n    print 1;\n\n\n    print 2;\n}",
+        "print [aaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbbb, cccccccccccccccccccccc, dddd, eeee];",
+    ];
+
+    #[test]
+    fn formats_the_canonical_style() {
+        assert_eq!(
+            format("var   x=1+2*3;print x ;"),
+            "var x = 1 + 2 * 3;\nprint x;\n"
+        );
+        assert_eq!(
+            format("if(a){print 1;}else if(b)print 2;else{print 3;}"),
+            "if (a) {\n    print 1;\n} else if (b) print 2;\nelse {\n    print 3;\n}\n"
+        );
+        assert_eq!(format(""), "");
+    }
+
+    #[test]
+    fn formatting_formatted_source_changes_nothing() {
+        for source in SOURCES {
+            let once = format(source);
+            assert_eq!(format(&once), once, "formatting `{}` twice", source);
+        }
+    }
+
+    #[test]
+    fn formatting_keeps_every_token_and_comment() {
+        for source in SOURCES {
+            let formatted = format(source);
+            assert_eq!(
+                significant_text(source).unwrap(),
+                significant_text(&formatted).unwrap(),
+                "`{}` formatted as `{}`",
+                source,
+                formatted
+            );
+        }
+    }
+
+    #[test]
+    fn comments_stay_where_they_were() {
+        assert_eq!(
+            format("// Own line\nvar x = 1; // after a statement\n\n\n\nprint x;"),
+            "// Own line\nvar x = 1; // after a statement\n\nprint x;\n"
+        );
+        assert_eq!(
+            format("/* block */var y = /* inside */ 2;\n/* spanning\n   lines */\nprint y;"),
+            "/* block */ var y = /* inside */ 2;\n/* spanning\n   lines */\nprint y;\n"
+        );
+        // A comment inside a statement indents the line after it
+        assert_eq!(format("print 1 + // one\n2;"), "print 1 + // one\n    2;\n");
+    }
+
+    #[test]
+    fn comments_in_a_list_put_it_one_item_per_line() {
+        assert_eq!(
+            format("var list = [1, // one\n2];"),
+            "var list = [\n    1, // one\n    2\n];\n"
+        );
+        assert_eq!(
+            format("print f( // first\na, b);"),
+            "print f( // first\n    a,\n    b\n);\n"
+        );
+    }
+
+    #[test]
+    fn long_lists_go_one_item_per_line() {
+        let names = ["a"; 30].map(|name| name.repeat(5)).join(", ");
+        let formatted = format(&format!("print [{}];", names));
+        assert!(
+            formatted.starts_with("print [\n    aaaaa,\n    aaaaa,\n"),
+            "{}",
+            formatted
+        );
+        assert!(formatted.ends_with("\n    aaaaa\n];\n"), "{}", formatted);
+        assert_eq!(format("print [aaaaa, aaaaa];"), "print [aaaaa, aaaaa];\n");
+    }
+
+    #[test]
+    fn code_that_does_not_parse_is_left_alone() {
+        assert!(format_source("print (1;").is_err());
+        assert!(format_source("var = 2;").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+
+/// An interned identifier. Symbols for equal names share one allocation, so comparing and
+/// hashing them is a pointer operation and reading one takes no lock.
+///
+/// A program has few distinct names however long it runs, so the interner keeps them for
+/// the rest of the process. String literals are interned too, but by the `Ast` they are
+/// parsed into (see `Ast::intern_str`), so a long REPL session doesn't leak them.
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+impl Symbol {
+    pub fn intern(name: &str) -> Self {
+        interner()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .intern(name)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ptr().hash(state)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Symbol({:?})", self.0)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+#[derive(Default)]
+struct Interner {
+    names: HashSet<&'static str>,
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(name) = self.names.get(name) {
+            return Symbol(name);
+        }
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        self.names.insert(name);
+        Symbol(name)
+    }
+}
+
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| Mutex::new(Interner::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_names_are_one_symbol() {
+        let a = Symbol::intern("counter");
+        let b = Symbol::intern(&String::from("counter"));
+        assert_eq!(a, b);
+        assert!(std::ptr::eq(a.as_str(), b.as_str()));
+        assert_ne!(a, Symbol::intern("counters"));
+        assert_eq!(a.to_string(), "counter");
+    }
+
+    #[test]
+    fn empty_names_are_interned_too() {
+        assert_eq!(Symbol::intern(""), Symbol::intern(""));
+        assert_ne!(Symbol::intern(""), Symbol::intern("x"));
+    }
+}
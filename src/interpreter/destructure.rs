@@ -0,0 +1,245 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::interpreter::{runtime_error, type_error, Failure};
+use crate::interpreter::map::missing_key;
+use crate::interpreter::value::Value;
+use crate::interpreter::Interpreter;
+use crate::parser::{Ast, ExprId, ExprKind, Pattern};
+use crate::{Error, ErrorKind};
+use std::rc::Rc;
+
+/// Declares the names of a pattern in `environment`, taking `value` apart to match it.
+/// A value that doesn't match is an error, on which a match goes on to its next arm.
+pub(crate) fn bind(
+    pattern: &Pattern,
+    value: Value,
+    environment: &mut Environment,
+) -> Result<(), Failure> {
+    match pattern {
+        Pattern::Name(name) | Pattern::Rest(name) => environment.define(*name, value),
+        Pattern::Tuple(items) | Pattern::List(items) => {
+            let collect = match pattern {
+                Pattern::Tuple(_) => Value::tuple,
+                _ => Value::list,
+            };
+            let rest = items
+                .iter()
+                .position(|item| matches!(item, Pattern::Rest(_)));
+            let values = unpack(&value, items.len(), rest, collect)?;
+            for (item, value) in items.iter().zip(values) {
+                bind(item, value, environment)?;
+            }
+        }
+        Pattern::Map(fields) => {
+            let map = match &value {
+                Value::Map(map) => map,
+                _ => return Err(type_error(not_a_map(&value))),
+            };
+            for (key, pattern) in fields {
+                let key = Value::Str(Rc::from(key.as_str()));
+                let item = map.borrow().get(&key).cloned();
+                let item = item.ok_or_else(|| (ErrorKind::Key, missing_key(&key)))?;
+                bind(pattern, item, environment)?;
+            }
+        }
+        Pattern::Wildcard => {}
+        Pattern::Literal(literal) => {
+            if value != Value::from(literal) {
+                let message = format!("Expected {}, got {}", literal, value.repr());
+                return Err((ErrorKind::Value, message));
+            }
+        }
+        Pattern::Range(start, end, inclusive) => {
+            let contains = match value {
+                Value::Number(n) => *start <= n && (n < *end || (*inclusive && n == *end)),
+                _ => false,
+            };
+            if !contains {
+                let message = format!("Expected a number in {}, got {}", pattern, value.repr());
+                return Err((ErrorKind::Value, message));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The values of a tuple or list for `len` targets, in the same order. The target at
+/// position `rest` gets the values the others leave over, collected with `collect`.
+fn unpack(
+    value: &Value,
+    len: usize,
+    rest: Option<usize>,
+    collect: fn(Vec<Value>) -> Value,
+) -> Result<Vec<Value>, Failure> {
+    let mut values = match value {
+        Value::Tuple(values) => values.to_vec(),
+        Value::List(values) => values.borrow().clone(),
+        _ => {
+            return Err(type_error(format!(
+                "Can only unpack a tuple or list, not {}",
+                value.type_name()
+            )))
+        }
+    };
+    match rest {
+        None if values.len() != len => Err((
+            ErrorKind::Value,
+            format!("Expected {} values to unpack, got {}", len, values.len()),
+        )),
+        None => Ok(values),
+        Some(_) if values.len() < len - 1 => Err((
+            ErrorKind::Value,
+            format!(
+                "Expected at least {} values to unpack, got {}",
+                len - 1,
+                values.len()
+            ),
+        )),
+        Some(position) => {
+            let after = values.split_off(values.len() - (len - 1 - position));
+            let collected = values.split_off(position);
+            values.push(collect(collected));
+            values.extend(after);
+            Ok(values)
+        }
+    }
+}
+
+fn not_a_map(value: &Value) -> String {
+    format!("Can only unpack a map, not {}", value.type_name())
+}
+
+impl Interpreter {
+    /// Assigns `value` to a tuple, list or map literal of targets, see `is_assignable` in
+    /// the parser. Each target is evaluated only when its value is stored.
+    pub(crate) fn destructure(
+        &mut self,
+        ast: &Ast,
+        target: ExprId,
+        value: Value,
+    ) -> Result<(), Error> {
+        match &ast[target].kind {
+            ExprKind::Tuple(elements) | ExprKind::List(elements) => {
+                let collect = match ast[target].kind {
+                    ExprKind::Tuple(_) => Value::tuple,
+                    _ => Value::list,
+                };
+                let rest = elements
+                    .iter()
+                    .position(|element| matches!(ast[*element].kind, ExprKind::Spread(_)));
+                let values = unpack(&value, elements.len(), rest, collect)
+                    .map_err(|(kind, message)| runtime_error(ast, target, kind, &message))?;
+                for (element, value) in elements.iter().zip(values) {
+                    match ast[*element].kind {
+                        ExprKind::Spread(rest) => self.destructure(ast, rest, value)?,
+                        _ => self.destructure(ast, *element, value)?,
+                    }
+                }
+                Ok(())
+            }
+            ExprKind::Map(entries) => {
+                let map = match &value {
+                    Value::Map(map) => map,
+                    _ => {
+                        let message = not_a_map(&value);
+                        return Err(runtime_error(ast, target, ErrorKind::Type, &message));
+                    }
+                };
+                for (key, element) in entries {
+                    let key_value = self.evaluate(ast, *key)?;
+                    let item = map.borrow().get(&key_value).cloned();
+                    let item = item.ok_or_else(|| {
+                        self.error_at(ast, *key, ErrorKind::Key, &missing_key(&key_value))
+                    })?;
+                    self.destructure(ast, *element, item)?;
+                }
+                Ok(())
+            }
+            _ => self.assign(ast, target, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Symbol;
+
+    fn numbers(values: &[f64]) -> Vec<Value> {
+        values.iter().map(|n| Value::Number(*n)).collect()
+    }
+
+    fn name(name: &str) -> Pattern {
+        Pattern::Name(Symbol::intern(name))
+    }
+
+    /// The values `pattern` binds its names to, shown like `print` does.
+    fn bound(pattern: &Pattern, value: Value) -> Result<Vec<String>, Failure> {
+        let mut environment = Environment::new();
+        bind(pattern, value, &mut environment)?;
+        Ok(pattern
+            .names()
+            .into_iter()
+            .map(|name| environment.get(name).unwrap().to_string())
+            .collect())
+    }
+
+    #[test]
+    fn a_rest_collects_what_the_other_items_leave() {
+        let pattern = Pattern::List(vec![
+            name("a"),
+            Pattern::Rest(Symbol::intern("b")),
+            name("c"),
+        ]);
+        let value = Value::list(numbers(&[1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(bound(&pattern, value).unwrap(), ["1", "[2, 3]", "4"]);
+        let value = Value::tuple(numbers(&[1.0, 2.0]));
+        assert_eq!(bound(&pattern, value).unwrap(), ["1", "[]", "2"]);
+        // A tuple pattern collects into a tuple
+        let pattern = Pattern::Tuple(vec![Pattern::Rest(Symbol::intern("r"))]);
+        let value = Value::list(numbers(&[1.0]));
+        assert_eq!(bound(&pattern, value).unwrap(), ["(1,)"]);
+    }
+
+    #[test]
+    fn shape_mismatches_are_errors() {
+        let pair = Pattern::Tuple(vec![name("a"), name("b")]);
+        assert_eq!(
+            bound(&pair, Value::tuple(numbers(&[1.0]))),
+            Err((
+                ErrorKind::Value,
+                String::from("Expected 2 values to unpack, got 1")
+            ))
+        );
+        assert_eq!(
+            bound(&pair, Value::Number(1.0)),
+            Err((
+                ErrorKind::Type,
+                String::from("Can only unpack a tuple or list, not number")
+            ))
+        );
+        let rest = Pattern::List(vec![
+            name("a"),
+            name("b"),
+            Pattern::Rest(Symbol::intern("c")),
+        ]);
+        assert_eq!(
+            bound(&rest, Value::list(numbers(&[1.0]))),
+            Err((
+                ErrorKind::Value,
+                String::from("Expected at least 2 values to unpack, got 1")
+            ))
+        );
+        let fields = Pattern::Map(vec![(Symbol::intern("x"), name("x"))]);
+        assert_eq!(
+            bound(&fields, Value::Nil),
+            Err((
+                ErrorKind::Type,
+                String::from("Can only unpack a map, not nil")
+            ))
+        );
+        assert_eq!(
+            bound(&fields, Value::map(Default::default())),
+            Err((ErrorKind::Key, String::from("Key \"x\" not found in map")))
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use crate::interpreter::value::Value;
+use crate::Symbol;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The variables of one scope, linked to the scope around it. Scopes are shared, so a
+/// function keeps the scope it was declared in alive as its closure.
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<Symbol, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Declares a variable in this scope, shadowing any outer one.
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    /// Updates the innermost variable with the name. Returns false if there is none.
+    pub fn assign(&mut self, name: Symbol, value: Value) -> bool {
+        if let Some(variable) = self.values.get_mut(&name) {
+            *variable = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        if let Some(value) = self.values.get(&name) {
+            return Some(value.clone());
+        }
+        self.enclosing.as_ref()?.borrow().get(name)
+    }
+}
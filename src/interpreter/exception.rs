@@ -0,0 +1,248 @@
+use crate::interpreter::value::Value;
+use crate::{ErrorKind, Span, Symbol};
+use std::fmt;
+use std::rc::Rc;
+
+// Recursion through up to this many functions is collapsed in a stack trace
+const MAX_CYCLE: usize = 4;
+// Calls that repeat fewer times than this are all shown
+const MIN_REPEATS: usize = 3;
+
+/// A place in the code, in a module or, for `None`, the main program.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Location {
+    pub(crate) span: Span,
+    pub(crate) file: Option<Rc<str>>,
+}
+
+/// A call in progress: the function, or `<module>` for the code of an imported module,
+/// and where it was called from.
+#[derive(Debug, Clone)]
+pub(crate) struct Frame {
+    pub(crate) function: &'static str,
+    pub(crate) call: Location,
+}
+
+/// An error in flight: where it was raised and the calls it has returned from so far,
+/// innermost first.
+#[derive(Debug, Default)]
+pub(crate) struct Unwinding {
+    pub(crate) raised: Option<Location>,
+    pub(crate) frames: Vec<Frame>,
+}
+
+/// A call that was active when an error was raised, and the position it had reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: &'static str,
+    /// The module the position is in, or `None` for the main program.
+    pub file: Option<Rc<str>>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl StackFrame {
+    /// The stack of an error raised at `raised`. `frames` are the calls that were active,
+    /// outermost first.
+    pub(crate) fn stack(raised: Location, frames: &[Frame]) -> Vec<StackFrame> {
+        let mut stack = Vec::with_capacity(frames.len() + 1);
+        let mut current = raised;
+        for frame in frames.iter().rev() {
+            stack.push(StackFrame::new(frame.function, current));
+            current = frame.call.clone();
+        }
+        stack.push(StackFrame::new("<script>", current));
+        stack
+    }
+
+    fn new(function: &'static str, location: Location) -> Self {
+        StackFrame {
+            function,
+            file: location.file,
+            line: location.span.line,
+            column: location.span.column,
+        }
+    }
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (", self.function)?;
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}:{})", self.line, self.column)
+    }
+}
+
+/// The lines of a trace of `stack` for a main program from `file`, innermost call first.
+/// Calls that recursion keeps repeating are shown once, followed by how often they repeat.
+pub fn stack_trace(stack: &[StackFrame], file: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut i = 0;
+    while i < stack.len() {
+        let cycle = (1..=MAX_CYCLE).find_map(|len| {
+            let calls = stack.get(i..i + len)?;
+            let repeats = stack[i..]
+                .chunks_exact(len)
+                .take_while(|chunk| *chunk == calls)
+                .count();
+            (repeats >= MIN_REPEATS).then_some((len, repeats))
+        });
+        let (len, repeats) = cycle.unwrap_or((1, 1));
+        for frame in &stack[i..i + len] {
+            lines.push(format!(
+                "    at {} ({}:{}:{})",
+                frame.function,
+                frame.file.as_deref().unwrap_or(file),
+                frame.line,
+                frame.column
+            ));
+        }
+        if repeats > 1 {
+            let calls = if len == 1 {
+                "call".to_string()
+            } else {
+                format!("{} calls", len)
+            };
+            lines.push(format!(
+                "    ... previous {} repeated {} more times",
+                calls,
+                repeats - 1
+            ));
+        }
+        i += len * repeats;
+    }
+    lines
+}
+
+/// A runtime error as a value, which is what a `catch` gets when the interpreter raised the
+/// error instead of a `throw`.
+#[derive(Debug)]
+pub struct Exception {
+    pub kind: ErrorKind,
+    pub message: Rc<str>,
+    pub line: usize,
+    /// Every call that was active, innermost first.
+    pub stack: Vec<StackFrame>,
+}
+
+impl Exception {
+    pub(crate) fn new(kind: ErrorKind, message: &str, line: usize, stack: Vec<StackFrame>) -> Self {
+        Exception {
+            kind,
+            message: Rc::from(message),
+            line,
+            stack,
+        }
+    }
+
+    pub(crate) fn property(&self, name: Symbol) -> Option<Value> {
+        let value = match name.as_str() {
+            "kind" => Value::Str(Rc::from(self.kind.to_string())),
+            "message" => Value::Str(Rc::clone(&self.message)),
+            "line" => Value::Number(self.line as f64),
+            "stack" => Value::list(
+                self.stack
+                    .iter()
+                    .map(|frame| Value::Str(Rc::from(frame.to_string())))
+                    .collect(),
+            ),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(function: &'static str, line: usize) -> StackFrame {
+        StackFrame {
+            function,
+            file: None,
+            line,
+            column: 1,
+        }
+    }
+
+    #[test]
+    fn the_stack_runs_from_the_error_out_through_each_call() {
+        let location = |line| Location {
+            span: Span {
+                line,
+                column: 1,
+                ..Span::default()
+            },
+            file: None,
+        };
+        let frames = [
+            Frame {
+                function: "outer",
+                call: location(9),
+            },
+            Frame {
+                function: "inner",
+                call: location(5),
+            },
+        ];
+        assert_eq!(
+            StackFrame::stack(location(2), &frames),
+            [frame("inner", 2), frame("outer", 5), frame("<script>", 9)]
+        );
+    }
+
+    #[test]
+    fn frames_show_their_module_or_the_main_file() {
+        let mut imported = frame("f", 3);
+        imported.file = Some(Rc::from("lib.seid"));
+        assert_eq!(
+            stack_trace(&[imported, frame("<script>", 7)], "main.seid"),
+            ["    at f (lib.seid:3:1)", "    at <script> (main.seid:7:1)"]
+        );
+    }
+
+    #[test]
+    fn recursion_is_collapsed() {
+        let mut stack = vec![frame("down", 2); 10];
+        stack.push(frame("<script>", 4));
+        assert_eq!(
+            stack_trace(&stack, "main"),
+            [
+                "    at down (main:2:1)",
+                "    ... previous call repeated 9 more times",
+                "    at <script> (main:4:1)"
+            ]
+        );
+    }
+
+    #[test]
+    fn mutual_recursion_is_collapsed_as_a_cycle() {
+        let mut stack = Vec::new();
+        for _ in 0..5 {
+            stack.extend([frame("even", 2), frame("odd", 6)]);
+        }
+        assert_eq!(
+            stack_trace(&stack, "main"),
+            [
+                "    at even (main:2:1)",
+                "    at odd (main:6:1)",
+                "    ... previous 2 calls repeated 4 more times"
+            ]
+        );
+    }
+
+    #[test]
+    fn a_few_repeats_are_all_shown() {
+        let stack = [frame("f", 2), frame("f", 2), frame("<script>", 4)];
+        assert_eq!(
+            stack_trace(&stack, "main"),
+            [
+                "    at f (main:2:1)",
+                "    at f (main:2:1)",
+                "    at <script> (main:4:1)"
+            ]
+        );
+    }
+}
@@ -0,0 +1,1630 @@
+use crate::interpreter::destructure::bind;
+use crate::interpreter::environment::Environment;
+use crate::interpreter::exception::{Exception, Frame, Location, StackFrame, Unwinding};
+use crate::interpreter::iterator::Iter;
+use crate::interpreter::list;
+use crate::interpreter::loader::{Loader, SearchPath};
+use crate::interpreter::map::{self, Map};
+use crate::interpreter::natives;
+use crate::interpreter::value::{Body, Exports, Function, Method, Value};
+use crate::parser::{Ast, BinaryOp, ExprId, ExprKind, LogicalOp, StmtId, StmtKind, UnaryOp};
+use crate::{Error, ErrorKind, Span, Symbol};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+// Largest magnitude up to which every integer is an f64, the range of the bitwise operators
+const MAX_EXACT_INTEGER: f64 = (1u64 << 53) as f64;
+// Deeper recursion is reported as an error instead of overflowing the native stack
+const MAX_CALL_DEPTH: usize = 5000;
+
+/// Where an assignment stores its value, with any sub-expressions of the target already
+/// evaluated, so that `x += 1` looks up `x` only once.
+enum Place {
+    Variable(Symbol),
+    Element(Rc<RefCell<Vec<Value>>>, usize),
+    Entry(Rc<RefCell<Map>>, Value),
+    /// An element of a tuple, which can be read but not assigned.
+    Item(Rc<[Value]>, usize),
+}
+
+/// How a statement finished. A `return` unwinds through the enclosing blocks to its call,
+/// `break` and `continue` to the loop with their label, or else the innermost one.
+enum Flow {
+    Next,
+    Return(Value),
+    Break(Option<Symbol>),
+    Continue(Option<Symbol>),
+}
+
+/// Executes statements by walking the AST. Variables live as long as the interpreter, so
+/// a REPL session keeps its globals between entries.
+pub struct Interpreter {
+    environment: Rc<RefCell<Environment>>,
+    // The calls in progress, outermost first
+    frames: Vec<Frame>,
+    // Where the error in flight was raised and the calls it has returned from so far
+    unwinding: Unwinding,
+    // The value of the `throw` that an `Error::Thrown` in flight stands for
+    thrown: Option<Value>,
+    // The module whose code is running, or `None` for the main program
+    file: Option<Rc<str>>,
+    loader: Loader,
+    // The exports of the modules that have run, by their index in the loader
+    modules: HashMap<usize, Rc<Exports>>,
+}
+
+impl Interpreter {
+    /// An interpreter whose imports search the directories listed in `SEID_PATH`.
+    pub fn new() -> Self {
+        Interpreter::with_search_path(SearchPath::from_env())
+    }
+
+    pub fn with_search_path(search_path: SearchPath) -> Self {
+        let mut globals = Environment::new();
+        natives::define(&mut globals);
+        Interpreter {
+            environment: Rc::new(RefCell::new(globals)),
+            frames: Vec::new(),
+            unwinding: Unwinding::default(),
+            thrown: None,
+            file: None,
+            loader: Loader::new(search_path),
+            modules: HashMap::new(),
+        }
+    }
+
+    /// Finds and parses the modules that `statements` import, which must happen before
+    /// the statements run. `file` is the file they come from, if any, for resolving
+    /// relative paths. Returns the warnings from parsing the modules.
+    pub fn load(
+        &mut self,
+        ast: &mut Ast,
+        statements: &[StmtId],
+        file: Option<&Path>,
+    ) -> Result<Vec<String>, Error> {
+        self.loader.load(ast, statements, file)
+    }
+
+    pub fn execute(&mut self, ast: &Ast, id: StmtId) -> Result<(), Error> {
+        // Left over from an error that nothing caught
+        self.take_stack_trace();
+        self.statement(ast, id)?;
+        Ok(())
+    }
+
+    /// The calls that were active when the last error that nothing caught was raised,
+    /// innermost first.
+    pub fn take_stack_trace(&mut self) -> Vec<StackFrame> {
+        let unwinding = std::mem::take(&mut self.unwinding);
+        match self.thrown.take() {
+            // A rethrown error keeps the stack of where it was first raised
+            Some(Value::Error(exception)) => exception.stack.clone(),
+            _ => self.stack(unwinding),
+        }
+    }
+
+    fn stack(&self, unwinding: Unwinding) -> Vec<StackFrame> {
+        let mut frames = self.frames.clone();
+        frames.extend(unwinding.frames.into_iter().rev());
+        StackFrame::stack(unwinding.raised.unwrap_or_default(), &frames)
+    }
+
+    fn statement(&mut self, ast: &Ast, id: StmtId) -> Result<Flow, Error> {
+        let result = self.run_statement(ast, id);
+        if result.is_err() {
+            self.raised(ast[id].span);
+        }
+        result
+    }
+
+    /// Notes where an error is raised. Every expression and statement that an error passes
+    /// through calls this, and only the innermost one is where it was raised.
+    fn raised(&mut self, span: Span) {
+        if self.unwinding.raised.is_none() {
+            let file = self.file.clone();
+            self.unwinding.raised = Some(Location { span, file });
+        }
+    }
+
+    /// A runtime error reported at `id` while evaluating something around it, e.g. at the
+    /// index of `xs[i]`. The stack trace points at `id` too, not at where evaluation was.
+    pub(crate) fn error_at(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        kind: ErrorKind,
+        message: &str,
+    ) -> Error {
+        self.raised(ast[id].span);
+        runtime_error(ast, id, kind, message)
+    }
+
+    fn run_statement(&mut self, ast: &Ast, id: StmtId) -> Result<Flow, Error> {
+        match &ast[id].kind {
+            StmtKind::Expression(expr) => {
+                self.evaluate(ast, *expr)?;
+            }
+            StmtKind::Print(expr) => {
+                let value = self.evaluate(ast, *expr)?;
+                println!("{}", value);
+            }
+            StmtKind::Var(pattern, initializer) => {
+                let value = match initializer {
+                    Some(expr) => self.evaluate(ast, *expr)?,
+                    None => Value::Nil,
+                };
+                bind(pattern, value, &mut self.environment.borrow_mut()).map_err(
+                    |(kind, message)| {
+                        Error::RuntimeError(format!("{}", ast[id].span.line), kind, message)
+                    },
+                )?;
+            }
+            StmtKind::Function(name, params, body) => {
+                let function = Value::Function(Rc::new(Function {
+                    name: Some(*name),
+                    params: params.clone(),
+                    body: Body::Block(body.clone()),
+                    closure: Rc::clone(&self.environment),
+                    module: self.file.clone(),
+                }));
+                self.environment.borrow_mut().define(*name, function);
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(expr) => self.evaluate(ast, *expr)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Return(value));
+            }
+            StmtKind::Block(stmts) => {
+                let environment = self.scope();
+                return self.block(ast, stmts, environment);
+            }
+            StmtKind::If(condition, then_branch, else_branch) => {
+                if self.evaluate(ast, *condition)?.is_truthy() {
+                    return self.statement(ast, *then_branch);
+                }
+                if let Some(else_branch) = else_branch {
+                    return self.statement(ast, *else_branch);
+                }
+            }
+            StmtKind::While(..) | StmtKind::For(..) | StmtKind::ForIn(..) => {
+                return self.run_loop(ast, id, None);
+            }
+            StmtKind::Labeled(label, body) => return self.run_loop(ast, *body, Some(*label)),
+            StmtKind::Throw(value) => {
+                let value = self.evaluate(ast, *value)?;
+                let error = Error::Thrown(format!("{}", ast[id].span.line), value.to_string());
+                self.thrown = Some(value);
+                return Err(error);
+            }
+            StmtKind::Try(body, catch, finally) => {
+                let mut result = self.block(ast, body, self.scope());
+                if let (Some((name, handler)), Err(error)) = (catch, &result) {
+                    if let Some(value) = self.caught(error) {
+                        let mut environment = self.scope();
+                        environment.define(*name, value);
+                        result = self.block(ast, handler, environment);
+                    }
+                }
+                if let Some(finally) = finally {
+                    // Runs however the rest finished, and only a jump out of it or an
+                    // error in it replaces that. A pending throw is set aside so that a
+                    // catch inside it can't take the thrown value.
+                    let unwinding = std::mem::take(&mut self.unwinding);
+                    let thrown = self.thrown.take();
+                    match self.block(ast, finally, self.scope())? {
+                        Flow::Next => {
+                            self.unwinding = unwinding;
+                            self.thrown = thrown;
+                        }
+                        flow => return Ok(flow),
+                    }
+                }
+                return result;
+            }
+            StmtKind::Import(_, name) => {
+                let module = self.import(ast, id)?;
+                self.environment
+                    .borrow_mut()
+                    .define(*name, Value::Module(module));
+            }
+            StmtKind::FromImport(_, names) => {
+                let module = self.import(ast, id)?;
+                for name in names {
+                    // The loader checked that the module exports the name
+                    let value = module.get(*name).unwrap_or(Value::Nil);
+                    self.environment.borrow_mut().define(*name, value);
+                }
+            }
+            StmtKind::Export(declaration) => return self.statement(ast, *declaration),
+            StmtKind::Break(label) => return Ok(Flow::Break(*label)),
+            StmtKind::Continue(label) => return Ok(Flow::Continue(*label)),
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Runs a `while`, `for` or `for ... in` loop, which `label` names if it has one.
+    fn run_loop(&mut self, ast: &Ast, id: StmtId, label: Option<Symbol>) -> Result<Flow, Error> {
+        match &ast[id].kind {
+            StmtKind::While(condition, body) => {
+                while self.evaluate(ast, *condition)?.is_truthy() {
+                    if let Some(flow) = self.iteration(ast, *body, label)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            StmtKind::For(initializer, condition, increment, body) => {
+                // The initializer's variable is scoped to the loop
+                let environment = self.scope();
+                return self.with_environment(environment, |interpreter| {
+                    if let Some(initializer) = initializer {
+                        interpreter.statement(ast, *initializer)?;
+                    }
+                    loop {
+                        if let Some(condition) = condition {
+                            if !interpreter.evaluate(ast, *condition)?.is_truthy() {
+                                return Ok(Flow::Next);
+                            }
+                        }
+                        if let Some(flow) = interpreter.iteration(ast, *body, label)? {
+                            return Ok(flow);
+                        }
+                        if let Some(increment) = increment {
+                            interpreter.evaluate(ast, *increment)?;
+                        }
+                    }
+                });
+            }
+            StmtKind::ForIn(pattern, iterable, body) => {
+                let value = self.evaluate(ast, *iterable)?;
+                let mut iter = Iter::new(value)
+                    .map_err(|message| self.error_at(ast, *iterable, ErrorKind::Type, &message))?;
+                while let Some(item) = self.next(ast, *iterable, &mut iter)? {
+                    // Every iteration gets its own variables, so closures keep their item
+                    let mut environment = self.scope();
+                    bind(pattern, item, &mut environment)
+                        .map_err(|(kind, message)| self.error_at(ast, *iterable, kind, &message))?;
+                    let flow = self.with_environment(environment, |interpreter| {
+                        interpreter.iteration(ast, *body, label)
+                    })?;
+                    if let Some(flow) = flow {
+                        return Ok(flow);
+                    }
+                }
+            }
+            _ => unreachable!("only loops can have a label"),
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Runs the body of a loop once. Returns how the loop finishes if it ends here: a
+    /// `break` or `continue` for an outer loop passes through to it.
+    fn iteration(
+        &mut self,
+        ast: &Ast,
+        body: StmtId,
+        label: Option<Symbol>,
+    ) -> Result<Option<Flow>, Error> {
+        Ok(match self.statement(ast, body)? {
+            Flow::Next | Flow::Continue(None) => None,
+            Flow::Continue(target) if target == label => None,
+            Flow::Break(None) => Some(Flow::Next),
+            Flow::Break(target) if target == label => Some(Flow::Next),
+            flow => Some(flow),
+        })
+    }
+
+    fn block(
+        &mut self,
+        ast: &Ast,
+        stmts: &[StmtId],
+        environment: Environment,
+    ) -> Result<Flow, Error> {
+        self.with_environment(environment, |interpreter| {
+            for stmt in stmts {
+                match interpreter.statement(ast, *stmt)? {
+                    Flow::Next => {}
+                    flow => return Ok(flow),
+                }
+            }
+            Ok(Flow::Next)
+        })
+    }
+
+    /// The exports of the module that the import `id` names, which runs the module in its
+    /// own globals if no import ran it before.
+    fn import(&mut self, ast: &Ast, id: StmtId) -> Result<Rc<Exports>, Error> {
+        let index = match self.loader.imports.get(id) {
+            Some(&index) => index,
+            None => {
+                let line = format!("{}", ast[id].span.line);
+                return Err(Error::ImportError(
+                    line,
+                    "Module was not loaded".to_string(),
+                ));
+            }
+        };
+        if let Some(exports) = self.modules.get(&index) {
+            return Ok(Rc::clone(exports));
+        }
+        let module = &self.loader.modules[index];
+        let (path, statements, names) = (
+            Rc::clone(&module.path),
+            module.statements.clone(),
+            module.exports.clone(),
+        );
+
+        let mut globals = Environment::new();
+        natives::define(&mut globals);
+        let globals = Rc::new(RefCell::new(globals));
+        let environment = std::mem::replace(&mut self.environment, Rc::clone(&globals));
+        let file = self.file.replace(Rc::clone(&path));
+        self.frames.push(Frame {
+            function: "<module>",
+            call: Location {
+                span: ast[id].span,
+                file: file.clone(),
+            },
+        });
+        let result = statements
+            .iter()
+            .try_for_each(|stmt| self.statement(ast, *stmt).map(drop));
+        let frame = self.frames.pop();
+        if result.is_err() {
+            self.unwinding.frames.extend(frame);
+        }
+        self.environment = environment;
+        self.file = file;
+        result?;
+
+        let exports = Rc::new(Exports {
+            path,
+            names,
+            globals,
+        });
+        self.modules.insert(index, Rc::clone(&exports));
+        Ok(exports)
+    }
+
+    /// A new scope inside the current one.
+    fn scope(&self) -> Environment {
+        Environment::with_enclosing(Rc::clone(&self.environment))
+    }
+
+    /// The value a `catch` gets for an error, or `None` for an error that can't be caught.
+    fn caught(&mut self, error: &Error) -> Option<Value> {
+        match error {
+            Error::Thrown(..) => {
+                self.unwinding = Unwinding::default();
+                Some(self.thrown.take().unwrap_or(Value::Nil))
+            }
+            Error::RuntimeError(line, kind, message) => {
+                let unwinding = std::mem::take(&mut self.unwinding);
+                let line = line.parse().unwrap_or_default();
+                Some(Value::Error(Rc::new(Exception::new(
+                    *kind,
+                    message,
+                    line,
+                    self.stack(unwinding),
+                ))))
+            }
+            _ => None,
+        }
+    }
+
+    /// Runs `f` in the given scope and restores the current one afterwards, also on errors.
+    fn with_environment<T>(
+        &mut self,
+        environment: Environment,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = f(self);
+        self.environment = previous;
+        result
+    }
+
+    pub fn evaluate(&mut self, ast: &Ast, id: ExprId) -> Result<Value, Error> {
+        let result = self.expression(ast, id);
+        if result.is_err() {
+            self.raised(ast[id].span);
+        }
+        result
+    }
+
+    fn expression(&mut self, ast: &Ast, id: ExprId) -> Result<Value, Error> {
+        match &ast[id].kind {
+            ExprKind::Literal(op) => Ok(Value::from(op)),
+            ExprKind::Variable(name) => self.read(ast, id, &Place::Variable(*name)),
+            ExprKind::Assign(target, None, value)
+                if matches!(
+                    ast[*target].kind,
+                    ExprKind::Tuple(_) | ExprKind::List(_) | ExprKind::Map(_)
+                ) =>
+            {
+                let value = self.evaluate(ast, *value)?;
+                self.destructure(ast, *target, value.clone())?;
+                Ok(value)
+            }
+            ExprKind::Assign(target, op, value) => {
+                let place = self.place(ast, *target)?;
+                let value = match op {
+                    Some(op) => {
+                        let current = self.read(ast, *target, &place)?;
+                        let value = self.evaluate(ast, *value)?;
+                        binary(*op, current, value)
+                            .map_err(|(kind, message)| runtime_error(ast, id, kind, &message))?
+                    }
+                    None => self.evaluate(ast, *value)?,
+                };
+                self.write(ast, *target, &place, value.clone())?;
+                Ok(value)
+            }
+            ExprKind::Increment(target, op, prefix) => {
+                let place = self.place(ast, *target)?;
+                let current = self.read(ast, *target, &place)?;
+                let updated = match current {
+                    Value::Number(n) if *op == BinaryOp::Add => Value::Number(n + 1.0),
+                    Value::Number(n) => Value::Number(n - 1.0),
+                    _ => {
+                        let operator = if *op == BinaryOp::Add { "++" } else { "--" };
+                        return Err(runtime_error(
+                            ast,
+                            id,
+                            ErrorKind::Type,
+                            &format!(
+                                "Operand of '{}' must be a number, not {}",
+                                operator,
+                                current.type_name()
+                            ),
+                        ));
+                    }
+                };
+                self.write(ast, *target, &place, updated.clone())?;
+                Ok(if *prefix { updated } else { current })
+            }
+            ExprKind::Grouping(expr) => self.evaluate(ast, *expr),
+            ExprKind::Unary(op, operand) => {
+                let value = self.evaluate(ast, *operand)?;
+                match (op, value) {
+                    (UnaryOp::Negate, Value::Number(n)) => Ok(Value::Number(-n)),
+                    (UnaryOp::Negate, value) => Err(runtime_error(
+                        ast,
+                        id,
+                        ErrorKind::Type,
+                        &format!("Operand of '-' must be a number, not {}", value.type_name()),
+                    )),
+                    (UnaryOp::Not, value) => Ok(Value::Bool(!value.is_truthy())),
+                    (UnaryOp::BitNot, value) => match integer(&value) {
+                        Some(n) => Ok(Value::Number(!n as f64)),
+                        None => Err(runtime_error(
+                            ast,
+                            id,
+                            ErrorKind::Type,
+                            &format!(
+                                "Operand of '~' must be an integer, not {}",
+                                describe(&value)
+                            ),
+                        )),
+                    },
+                }
+            }
+            ExprKind::Binary(left, op, right) => {
+                let left = self.evaluate(ast, *left)?;
+                let right = self.evaluate(ast, *right)?;
+                binary(*op, left, right)
+                    .map_err(|(kind, message)| runtime_error(ast, id, kind, &message))
+            }
+            ExprKind::Logical(left, op, right) => {
+                let left = self.evaluate(ast, *left)?;
+                let decided = match op {
+                    LogicalOp::And => !left.is_truthy(),
+                    LogicalOp::Or => left.is_truthy(),
+                };
+                if decided {
+                    Ok(left)
+                } else {
+                    self.evaluate(ast, *right)
+                }
+            }
+            ExprKind::Conditional(condition, then_branch, else_branch) => {
+                // Only the chosen branch is evaluated
+                if self.evaluate(ast, *condition)?.is_truthy() {
+                    self.evaluate(ast, *then_branch)
+                } else {
+                    self.evaluate(ast, *else_branch)
+                }
+            }
+            ExprKind::Call(callee, arguments) => {
+                let callee = self.evaluate(ast, *callee)?;
+                let arguments = self.elements(ast, arguments)?;
+                self.call(ast, id, callee, arguments)
+            }
+            ExprKind::Index(..) => {
+                let place = self.place(ast, id)?;
+                self.read(ast, id, &place)
+            }
+            ExprKind::Slice(object, start, end) => {
+                let object = self.evaluate(ast, *object)?;
+                let mut bounds = [None, None];
+                for (bound, expr) in bounds.iter_mut().zip([start, end]) {
+                    if let Some(expr) = expr {
+                        *bound = Some(self.evaluate(ast, *expr)?);
+                    }
+                }
+                let [start, end] = bounds;
+                let error = |message: String| runtime_error(ast, id, ErrorKind::Type, &message);
+                match object {
+                    Value::List(values) => Ok(Value::list(
+                        list::slice(&values.borrow(), start, end).map_err(error)?,
+                    )),
+                    Value::Tuple(values) => Ok(Value::tuple(
+                        list::slice(&values, start, end).map_err(error)?,
+                    )),
+                    _ => Err(runtime_error(
+                        ast,
+                        id,
+                        ErrorKind::Type,
+                        &format!(
+                            "Can only slice lists and tuples, not {}",
+                            object.type_name()
+                        ),
+                    )),
+                }
+            }
+            ExprKind::Get(object, name) => {
+                let object = self.evaluate(ast, *object)?;
+                match object {
+                    Value::List(_) if list::has_method(*name) => {
+                        Ok(Value::Method(Rc::new(Method {
+                            receiver: object,
+                            name: *name,
+                        })))
+                    }
+                    Value::Map(_) if map::has_method(*name) => Ok(Value::Method(Rc::new(Method {
+                        receiver: object,
+                        name: *name,
+                    }))),
+                    Value::Error(ref error) => error.property(*name).ok_or_else(|| {
+                        let message = format!("error has no property '{}'", name);
+                        runtime_error(ast, id, ErrorKind::Name, &message)
+                    }),
+                    Value::Module(ref module) => module.get(*name).ok_or_else(|| {
+                        let message =
+                            format!("Module '{}' does not export '{}'", module.path, name);
+                        runtime_error(ast, id, ErrorKind::Name, &message)
+                    }),
+                    _ => Err(runtime_error(
+                        ast,
+                        id,
+                        ErrorKind::Type,
+                        &format!("{} has no property '{}'", object.type_name(), name),
+                    )),
+                }
+            }
+            ExprKind::List(elements) => Ok(Value::list(self.elements(ast, elements)?)),
+            ExprKind::Tuple(elements) => Ok(Value::tuple(self.elements(ast, elements)?)),
+            ExprKind::Spread(_) => Err(runtime_error(
+                ast,
+                id,
+                ErrorKind::Type,
+                "'...' can only be used in a list, tuple or argument list",
+            )),
+            ExprKind::Map(entries) => {
+                let mut map = Map::new();
+                for (key, value) in entries {
+                    let key = self.evaluate(ast, *key)?;
+                    let value = self.evaluate(ast, *value)?;
+                    map.insert(key, value);
+                }
+                Ok(Value::map(map))
+            }
+            ExprKind::Match(value, arms) => {
+                let value = self.evaluate(ast, *value)?;
+                for arm in arms {
+                    let mut environment = self.scope();
+                    if bind(&arm.pattern, value.clone(), &mut environment).is_err() {
+                        continue;
+                    }
+                    // The guard sees the names the pattern declares
+                    let result = self.with_environment(environment, |interpreter| {
+                        if let Some(guard) = arm.guard {
+                            if !interpreter.evaluate(ast, guard)?.is_truthy() {
+                                return Ok(None);
+                            }
+                        }
+                        interpreter.evaluate(ast, arm.body).map(Some)
+                    })?;
+                    if let Some(result) = result {
+                        return Ok(result);
+                    }
+                }
+                Err(runtime_error(
+                    ast,
+                    id,
+                    ErrorKind::Match,
+                    &format!("No match arm matches {}", value.repr()),
+                ))
+            }
+            ExprKind::Lambda(params, body) => Ok(Value::Function(Rc::new(Function {
+                name: None,
+                params: params.clone(),
+                body: Body::Expr(*body),
+                closure: Rc::clone(&self.environment),
+                module: self.file.clone(),
+            }))),
+        }
+    }
+
+    /// Evaluates the elements of a list, tuple or argument list, splicing in spread ones.
+    fn elements(&mut self, ast: &Ast, ids: &[ExprId]) -> Result<Vec<Value>, Error> {
+        let mut values = Vec::with_capacity(ids.len());
+        for id in ids {
+            match ast[*id].kind {
+                ExprKind::Spread(spread) => match self.evaluate(ast, spread)? {
+                    Value::List(items) => values.extend(items.borrow().iter().cloned()),
+                    Value::Tuple(items) => values.extend(items.iter().cloned()),
+                    value => {
+                        return Err(runtime_error(
+                            ast,
+                            *id,
+                            ErrorKind::Type,
+                            &format!("Can only spread a list or tuple, not {}", value.type_name()),
+                        ))
+                    }
+                },
+                _ => values.push(self.evaluate(ast, *id)?),
+            }
+        }
+        Ok(values)
+    }
+
+    /// Calls a function or method with evaluated arguments. `id` is the call expression,
+    /// errors are reported at it.
+    pub(crate) fn call(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        callee: Value,
+        arguments: Vec<Value>,
+    ) -> Result<Value, Error> {
+        match callee {
+            Value::Function(function) => self.call_function(ast, id, &function, arguments),
+            Value::Native(native) => (native.function)(&arguments)
+                .map_err(|(kind, message)| runtime_error(ast, id, kind, &message)),
+            Value::Method(method) => match &method.receiver {
+                Value::List(values) => self.list_method(ast, id, values, method.name, arguments),
+                Value::Map(map) => self.map_method(ast, id, map, method.name, arguments),
+                receiver => Err(runtime_error(
+                    ast,
+                    id,
+                    ErrorKind::Type,
+                    &format!("{} has no methods", receiver.type_name()),
+                )),
+            },
+            _ => Err(runtime_error(
+                ast,
+                id,
+                ErrorKind::Type,
+                &format!(
+                    "Can only call functions and methods, not {}",
+                    callee.type_name()
+                ),
+            )),
+        }
+    }
+
+    fn call_function(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        function: &Function,
+        arguments: Vec<Value>,
+    ) -> Result<Value, Error> {
+        if arguments.len() != function.params.len() {
+            return Err(runtime_error(
+                ast,
+                id,
+                ErrorKind::Type,
+                &format!(
+                    "Expected {} arguments but got {}",
+                    function.params.len(),
+                    arguments.len()
+                ),
+            ));
+        }
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(runtime_error(
+                ast,
+                id,
+                ErrorKind::Recursion,
+                "Stack overflow",
+            ));
+        }
+        let mut environment = Environment::with_enclosing(Rc::clone(&function.closure));
+        for (param, argument) in function.params.iter().zip(arguments) {
+            bind(param, argument, &mut environment)
+                .map_err(|(kind, message)| runtime_error(ast, id, kind, &message))?;
+        }
+
+        self.frames.push(Frame {
+            function: function.name.map_or("<lambda>", |name| name.as_str()),
+            call: Location {
+                span: ast[id].span,
+                file: self.file.clone(),
+            },
+        });
+        let file = std::mem::replace(&mut self.file, function.module.clone());
+        let result = match &function.body {
+            Body::Block(stmts) => match self.block(ast, stmts, environment) {
+                Ok(Flow::Return(value)) => Ok(value),
+                // The parser keeps `break` and `continue` inside their function
+                Ok(_) => Ok(Value::Nil),
+                Err(e) => Err(e),
+            },
+            Body::Expr(body) => {
+                self.with_environment(environment, |interpreter| interpreter.evaluate(ast, *body))
+            }
+        };
+        self.file = file;
+        let frame = self.frames.pop();
+        if result.is_err() {
+            self.unwinding.frames.extend(frame);
+        }
+        result
+    }
+}
+
+impl Interpreter {
+    /// Stores a value in a plain target like `x` or `xs[i]`.
+    pub(crate) fn assign(&mut self, ast: &Ast, target: ExprId, value: Value) -> Result<(), Error> {
+        let place = self.place(ast, target)?;
+        self.write(ast, target, &place, value)
+    }
+
+    fn place(&mut self, ast: &Ast, target: ExprId) -> Result<Place, Error> {
+        match &ast[target].kind {
+            ExprKind::Variable(name) => Ok(Place::Variable(*name)),
+            ExprKind::Index(object, index) => {
+                let object = self.evaluate(ast, *object)?;
+                let index_value = self.evaluate(ast, *index)?;
+                match object {
+                    Value::List(values) => {
+                        let len = values.borrow().len();
+                        // Reported at the index, not the whole expression
+                        let i =
+                            list::index("List", len, &index_value).map_err(|(kind, message)| {
+                                self.error_at(ast, *index, kind, &message)
+                            })?;
+                        Ok(Place::Element(values, i))
+                    }
+                    Value::Tuple(values) => {
+                        let i = list::index("Tuple", values.len(), &index_value).map_err(
+                            |(kind, message)| self.error_at(ast, *index, kind, &message),
+                        )?;
+                        Ok(Place::Item(values, i))
+                    }
+                    Value::Map(map) => Ok(Place::Entry(map, index_value)),
+                    _ => Err(runtime_error(
+                        ast,
+                        target,
+                        ErrorKind::Type,
+                        &format!(
+                            "Can only index lists, tuples and maps, not {}",
+                            object.type_name()
+                        ),
+                    )),
+                }
+            }
+            _ => Err(runtime_error(
+                ast,
+                target,
+                ErrorKind::Type,
+                "Invalid assignment target",
+            )),
+        }
+    }
+
+    fn read(&self, ast: &Ast, target: ExprId, place: &Place) -> Result<Value, Error> {
+        match place {
+            Place::Variable(name) => match self.environment.borrow().get(*name) {
+                Some(value) => Ok(value),
+                None => Err(runtime_error(
+                    ast,
+                    target,
+                    ErrorKind::Name,
+                    &format!("Undefined variable '{}'", name),
+                )),
+            },
+            Place::Element(values, i) => Ok(values.borrow()[*i].clone()),
+            Place::Item(values, i) => Ok(values[*i].clone()),
+            Place::Entry(map, key) => match map.borrow().get(key) {
+                Some(value) => Ok(value.clone()),
+                None => Err(runtime_error(
+                    ast,
+                    target,
+                    ErrorKind::Key,
+                    &map::missing_key(key),
+                )),
+            },
+        }
+    }
+
+    fn write(
+        &mut self,
+        ast: &Ast,
+        target: ExprId,
+        place: &Place,
+        value: Value,
+    ) -> Result<(), Error> {
+        match place {
+            Place::Variable(name) => {
+                if !self.environment.borrow_mut().assign(*name, value) {
+                    return Err(runtime_error(
+                        ast,
+                        target,
+                        ErrorKind::Name,
+                        &format!("Undefined variable '{}'", name),
+                    ));
+                }
+            }
+            Place::Element(values, i) => {
+                let mut values = values.borrow_mut();
+                // The list may have shrunk while the value was evaluated
+                match values.get_mut(*i) {
+                    Some(element) => *element = value,
+                    None => {
+                        return Err(runtime_error(
+                            ast,
+                            target,
+                            ErrorKind::Index,
+                            &format!(
+                                "Index {} out of range for list of length {}",
+                                i,
+                                values.len()
+                            ),
+                        ))
+                    }
+                }
+            }
+            Place::Entry(map, key) => map.borrow_mut().insert(key.clone(), value),
+            Place::Item(..) => {
+                return Err(runtime_error(
+                    ast,
+                    target,
+                    ErrorKind::Type,
+                    "Can't assign to an element of a tuple",
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Errors come with their kind, a division by zero isn't a type error.
+fn binary(op: BinaryOp, left: Value, right: Value) -> Result<Value, Failure> {
+    let (a, b) = match (op, &left, &right) {
+        (BinaryOp::Equal, _, _) => return Ok(Value::Bool(left == right)),
+        (BinaryOp::NotEqual, _, _) => return Ok(Value::Bool(left != right)),
+        (BinaryOp::Comma, _, _) => return Ok(right),
+        (
+            BinaryOp::BitAnd
+            | BinaryOp::BitOr
+            | BinaryOp::BitXor
+            | BinaryOp::ShiftLeft
+            | BinaryOp::ShiftRight,
+            _,
+            _,
+        ) => return bitwise(op, &left, &right),
+        (BinaryOp::Add, Value::Str(a), Value::Str(b)) => {
+            return Ok(Value::Str(Rc::from(format!("{}{}", a, b))))
+        }
+        (_, Value::Number(a), Value::Number(b)) => (*a, *b),
+        (BinaryOp::Add, _, _) => {
+            return Err(type_error(format!(
+                "Operands of '+' must be two numbers or two strings, not {} and {}",
+                left.type_name(),
+                right.type_name()
+            )))
+        }
+        _ => {
+            return Err(type_error(format!(
+                "Operands of '{}' must be numbers, not {} and {}",
+                op,
+                left.type_name(),
+                right.type_name()
+            )))
+        }
+    };
+    let value = match op {
+        BinaryOp::Add => Value::Number(a + b),
+        BinaryOp::Subtract => Value::Number(a - b),
+        BinaryOp::Multiply => Value::Number(a * b),
+        BinaryOp::Divide => Value::Number(a / b),
+        // Like `~/`, and unlike `/` which gives an infinity, there is no remainder to give
+        BinaryOp::Modulo => {
+            if b == 0.0 {
+                return Err((ErrorKind::ZeroDivision, String::from("Modulo by zero")));
+            }
+            Value::Number(a % b)
+        }
+        BinaryOp::IntDivide => {
+            if b == 0.0 {
+                return Err((
+                    ErrorKind::ZeroDivision,
+                    String::from("Integer division by zero"),
+                ));
+            }
+            Value::Number((a / b).trunc())
+        }
+        BinaryOp::Power => Value::Number(a.powf(b)),
+        BinaryOp::Greater => Value::Bool(a > b),
+        BinaryOp::GreaterEqual => Value::Bool(a >= b),
+        BinaryOp::Less => Value::Bool(a < b),
+        BinaryOp::LessEqual => Value::Bool(a <= b),
+        _ => unreachable!("'{}' is handled before its operands are checked", op),
+    };
+    Ok(value)
+}
+
+fn bitwise(op: BinaryOp, left: &Value, right: &Value) -> Result<Value, Failure> {
+    let (a, b) = match (integer(left), integer(right)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            return Err(type_error(format!(
+                "Operands of '{}' must be integers, not {} and {}",
+                op,
+                describe(left),
+                describe(right)
+            )))
+        }
+    };
+    let value = match op {
+        BinaryOp::BitAnd => a & b,
+        BinaryOp::BitOr => a | b,
+        BinaryOp::BitXor => a ^ b,
+        _ => {
+            if !(0..64).contains(&b) {
+                let message = format!("Cannot shift by {}, expected 0 to 63", b);
+                return Err((ErrorKind::Value, message));
+            }
+            if op == BinaryOp::ShiftLeft {
+                a << b
+            } else {
+                a >> b
+            }
+        }
+    };
+    Ok(Value::Number(value as f64))
+}
+
+/// The value as an integer, if it is a number without a fractional part that an f64 holds
+/// exactly.
+pub(crate) fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_EXACT_INTEGER => Some(*n as i64),
+        _ => None,
+    }
+}
+
+/// Numbers are shown by value in errors about integers, e.g. why `1.5` doesn't qualify.
+pub(crate) fn describe(value: &Value) -> String {
+    match value {
+        Value::Number(n) => n.to_string(),
+        _ => value.type_name().to_string(),
+    }
+}
+
+/// An error of a helper that has no expression to report it at. The caller turns it into
+/// a runtime error with `runtime_error`.
+pub(crate) type Failure = (ErrorKind, String);
+
+/// The failure of a helper given a value of the wrong type.
+pub(crate) fn type_error(message: String) -> Failure {
+    (ErrorKind::Type, message)
+}
+
+pub(crate) fn runtime_error(ast: &Ast, id: ExprId, kind: ErrorKind, message: &str) -> Error {
+    Error::RuntimeError(format!("{}", ast[id].span.line), kind, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::stack_trace;
+    use crate::parser::Parser;
+    use crate::Scanner;
+
+    /// Runs `program`, then gives the value of `expression` as `print` shows it.
+    fn eval(program: &str, expression: &str) -> Result<String, Error> {
+        let mut ast = Ast::new();
+        let mut interpreter = Interpreter::new();
+        for id in Parser::new(Scanner::new(program), &mut ast)?.parse()? {
+            interpreter.execute(&ast, id)?;
+        }
+        let source = format!("{};", expression);
+        let statements = Parser::new(Scanner::new(&source), &mut ast)?.parse()?;
+        let StmtKind::Expression(id) = ast[statements[0]].kind else {
+            panic!("`{}` is not an expression", expression);
+        };
+        interpreter
+            .evaluate(&ast, id)
+            .map(|value| value.to_string())
+    }
+
+    fn value(expression: &str) -> String {
+        eval("", expression).unwrap_or_else(|e| panic!("`{}` failed: {}", expression, e))
+    }
+
+    /// The message of the runtime error that running `program` ends with.
+    fn failure(program: &str) -> String {
+        match eval(program, "nil") {
+            Err(Error::RuntimeError(_, _, message)) => message,
+            other => panic!("`{}` should fail, got {:?}", program, other),
+        }
+    }
+
+    /// The kind of the runtime error that running `program` ends with.
+    fn failure_kind(program: &str) -> ErrorKind {
+        match eval(program, "nil") {
+            Err(Error::RuntimeError(_, kind, _)) => kind,
+            other => panic!("`{}` should fail, got {:?}", program, other),
+        }
+    }
+
+    #[test]
+    fn runtime_errors_have_the_kind_they_were_raised_with() {
+        let cases = [
+            ("1 + nil;", ErrorKind::Type),
+            ("missing;", ErrorKind::Name),
+            ("[1][3];", ErrorKind::Index),
+            ("[].pop();", ErrorKind::Index),
+            ("({\"a\": 1})[\"b\"];", ErrorKind::Key),
+            ("1 ~/ 0;", ErrorKind::ZeroDivision),
+            ("1 % 0;", ErrorKind::ZeroDivision),
+            ("1 << 64;", ErrorKind::Value),
+            ("var (a, b) = (1, 2, 3);", ErrorKind::Value),
+            ("range(0, 1, 0);", ErrorKind::Value),
+            ("range(nil);", ErrorKind::Type),
+            ("print match (1) { 2 => nil };", ErrorKind::Match),
+        ];
+        for (program, kind) in cases {
+            assert_eq!(failure_kind(program), kind, "{}", program);
+        }
+    }
+
+    #[test]
+    fn a_catch_sees_the_kind_of_the_error() {
+        let program = "
+            var kinds = [];
+            try { missing; } catch (e) { kinds.push(e.kind); }
+            // The message of a key error mentions no kind, only the key
+            try { ({})[\"by zero\"]; } catch (e) { kinds.push(e.kind); }
+            try { 1 ~/ 0; } catch (e) { kinds.push(e.kind); }
+        ";
+        assert_eq!(
+            eval(program, "kinds").unwrap(),
+            "[\"NameError\", \"KeyError\", \"ZeroDivisionError\"]"
+        );
+    }
+
+    #[test]
+    fn finally_runs_on_a_normal_exit_a_return_and_a_jump() {
+        let program = "
+            var log = [];
+            try { log.push(1); } finally { log.push(\"normal\"); }
+            fun f() {
+                try { return 2; } finally { log.push(\"return\"); }
+            }
+            log.push(f());
+            for (i in [1, 2]) {
+                try {
+                    if (i == 1) continue;
+                    break;
+                } finally { log.push(i); }
+            }
+        ";
+        assert_eq!(
+            eval(program, "log").unwrap(),
+            "[1, \"normal\", \"return\", 2, 1, 2]"
+        );
+    }
+
+    #[test]
+    fn an_error_rethrown_from_a_catch_still_runs_finally() {
+        let program = "
+            var log = [];
+            try {
+                try { throw 1; } catch (e) { log.push(e); throw e + 1; } finally { log.push(\"finally\"); }
+            } catch (e) { log.push(e); }
+        ";
+        assert_eq!(eval(program, "log").unwrap(), "[1, \"finally\", 2]");
+    }
+
+    #[test]
+    fn a_return_or_throw_in_finally_replaces_the_pending_result() {
+        let program = "
+            fun returns() {
+                try { throw 1; } finally { return 2; }
+            }
+            fun throws() {
+                try { return 1; } finally { throw 2; }
+            }
+            var caught;
+            try { throws(); } catch (e) { caught = e; }
+        ";
+        assert_eq!(eval(program, "[returns(), caught]").unwrap(), "[2, 2]");
+    }
+
+    #[test]
+    fn a_catch_inside_finally_leaves_the_pending_throw_alone() {
+        let program = "
+            var log = [];
+            try {
+                try { throw 1; } finally {
+                    try { throw 2; } catch (e) { log.push(e); }
+                }
+            } catch (e) { log.push(e); }
+        ";
+        assert_eq!(eval(program, "log").unwrap(), "[2, 1]");
+    }
+
+    #[test]
+    fn evaluates_operators() {
+        assert_eq!(value("1 + 2 * 3"), "7");
+        assert_eq!(value("(1 + 2) * 3"), "9");
+        assert_eq!(value("-2 ** 2"), "-4");
+        assert_eq!(value("7 % 3 + 7 ~/ 2"), "4");
+        assert_eq!(value("\"con\" + \"cat\""), "concat");
+        assert_eq!(value("1 < 2 == !false"), "true");
+    }
+
+    #[test]
+    fn evaluates_arithmetic_and_bitwise_operators() {
+        assert_eq!(value("-7 % 3"), "-1");
+        assert_eq!(value("2 ** 3 ** 2"), "512");
+        assert_eq!(value("-7 ~/ 2"), "-3");
+        assert_eq!(value("6 & 3 | 8 ^ 1"), "11");
+        assert_eq!(value("~5"), "-6");
+        assert_eq!(value("1 << 10 >> 2"), "256");
+    }
+
+    #[test]
+    fn bitwise_operators_only_take_integers() {
+        assert_eq!(
+            failure("print 1.5 & 1;"),
+            "Operands of '&' must be integers, not 1.5 and 1"
+        );
+        assert_eq!(
+            failure("print 1 | \"a\";"),
+            "Operands of '|' must be integers, not 1 and string"
+        );
+        assert_eq!(
+            failure("print ~0.5;"),
+            "Operand of '~' must be an integer, not 0.5"
+        );
+        assert_eq!(
+            failure("print 1 << 64;"),
+            "Cannot shift by 64, expected 0 to 63"
+        );
+        assert_eq!(failure("print 1 ~/ 0;"), "Integer division by zero");
+        assert_eq!(failure("print 5 % 0;"), "Modulo by zero");
+        assert_eq!(failure("print 5 % -0;"), "Modulo by zero");
+        assert_eq!(
+            failure("print nil % 2;"),
+            "Operands of '%' must be numbers, not nil and number"
+        );
+    }
+
+    #[test]
+    fn variables_keep_their_values_between_statements() {
+        assert_eq!(eval("var a = 1; a = a + 1; a += 10;", "a").unwrap(), "12");
+    }
+
+    #[test]
+    fn compound_assignment_updates_variables_elements_and_entries() {
+        let program = "var a = 10; a -= 4; a *= 2; a /= 3; a %= 3;
+            var xs = [1, 2]; xs[1] += 5;
+            var m = {k: \"a\"}; m[\"k\"] += \"b\";";
+        assert_eq!(
+            eval(program, "[a, xs, m[\"k\"]]").unwrap(),
+            "[1, [1, 7], \"ab\"]"
+        );
+    }
+
+    #[test]
+    fn compound_assignment_evaluates_its_target_once() {
+        let program = "var calls = 0; var xs = [0, 0];
+            fun at() { calls += 1; return 1; }
+            xs[at()] += 5; xs[at()]++;";
+        assert_eq!(eval(program, "[calls, xs]").unwrap(), "[2, [0, 6]]");
+    }
+
+    #[test]
+    fn prefix_increments_give_the_new_value_and_postfix_the_old() {
+        let program = "var a = 1; var b = a++; var c = ++a; var d = a--; var e = --a;";
+        assert_eq!(eval(program, "[a, b, c, d, e]").unwrap(), "[1, 1, 3, 3, 1]");
+        assert_eq!(
+            failure("var s = \"a\"; s++;"),
+            "Operand of '++' must be a number, not string"
+        );
+    }
+
+    #[test]
+    fn functions_return_and_recurse() {
+        let program = "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            fun nothing() {}";
+        assert_eq!(eval(program, "[fib(10), nothing()]").unwrap(), "[55, nil]");
+    }
+
+    #[test]
+    fn closures_keep_the_scope_they_were_made_in() {
+        let program = "fun counter() { var n = 0; return lambda: ++n; }
+            var a = counter(); var b = counter(); a(); a();";
+        assert_eq!(eval(program, "[a(), b()]").unwrap(), "[3, 1]");
+        assert_eq!(value("(lambda x, y: x * y)(6, 7)"), "42");
+    }
+
+    #[test]
+    fn calls_check_the_callee_and_the_arguments() {
+        assert_eq!(
+            failure("fun f(a) {} f(1, 2);"),
+            "Expected 1 arguments but got 2"
+        );
+        assert_eq!(
+            failure("var x = 1; x();"),
+            "Can only call functions and methods, not number"
+        );
+    }
+
+    #[test]
+    fn deep_recursion_is_a_runtime_error() {
+        // Needs a stack like the one `main` runs the interpreter on
+        let result = std::thread::Builder::new()
+            .stack_size(crate::INTERPRETER_STACK_SIZE)
+            .spawn(|| failure("fun down(n) { return down(n + 1); } down(0);"))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(result, "Stack overflow");
+    }
+
+    /// The stack trace of the error that running `program` ends with.
+    fn trace(program: &str) -> Vec<String> {
+        let mut ast = Ast::new();
+        let mut interpreter = Interpreter::new();
+        for id in Parser::new(Scanner::new(program), &mut ast)
+            .unwrap()
+            .parse()
+            .unwrap()
+        {
+            if interpreter.execute(&ast, id).is_err() {
+                return stack_trace(&interpreter.take_stack_trace(), "main");
+            }
+        }
+        panic!("`{}` should fail", program);
+    }
+
+    #[test]
+    fn a_stack_trace_shows_every_call_innermost_first() {
+        let program =
+            "fun inner() {\n  return 1 + nil;\n}\nfun outer() {\n  return inner();\n}\nouter();";
+        assert_eq!(
+            trace(program),
+            [
+                "    at inner (main:2:10)",
+                "    at outer (main:5:10)",
+                "    at <script> (main:7:1)"
+            ]
+        );
+    }
+
+    #[test]
+    fn a_stack_trace_collapses_recursion() {
+        let program = "fun down(n) {\n  if (n == 0) missing;\n  down(n - 1);\n}\ndown(20);";
+        assert_eq!(
+            trace(program),
+            [
+                "    at down (main:2:15)",
+                "    at down (main:3:3)",
+                "    ... previous call repeated 19 more times",
+                "    at <script> (main:5:1)"
+            ]
+        );
+    }
+
+    #[test]
+    fn a_rethrown_error_keeps_the_stack_where_it_was_raised() {
+        let program = "fun f() {\n  missing;\n}\ntry { f(); } catch (e) {\n  throw e;\n}";
+        assert_eq!(
+            trace(program),
+            ["    at f (main:2:3)", "    at <script> (main:4:7)"]
+        );
+    }
+
+    #[test]
+    fn a_stack_trace_points_at_the_index_or_iterable_that_failed() {
+        assert_eq!(
+            trace("var xs = [1];\nprint xs[5];"),
+            ["    at <script> (main:2:10)"]
+        );
+        assert_eq!(
+            trace("var t = (1, 2);\nprint t[(7)];"),
+            ["    at <script> (main:2:9)"]
+        );
+        assert_eq!(
+            trace("var n = 5;\nfor (x in n) {}"),
+            ["    at <script> (main:2:11)"]
+        );
+    }
+
+    #[test]
+    fn a_list_that_contains_itself_can_be_printed_and_compared() {
+        let program = "var xs = [1]; xs.push(xs); var ys = [1]; ys.push(ys);";
+        assert_eq!(eval(program, "xs").unwrap(), "[1, [...]]");
+        assert_eq!(
+            eval(program, "[xs == xs, xs == ys]").unwrap(),
+            "[true, false]"
+        );
+    }
+
+    #[test]
+    fn maps_are_read_and_written_by_key() {
+        let program = "var name = \"x\"; var m = {a: 1, \"b\": 2, (name): 3, name};
+            m[\"a\"] = 10; m[[1]] = nil;";
+        assert_eq!(
+            eval(program, "m").unwrap(),
+            "{\"a\": 10, \"b\": 2, \"x\": 3, \"name\": \"x\", [1]: nil}"
+        );
+        assert_eq!(
+            failure("var m = {}; print m[\"a\"];"),
+            "Key \"a\" not found in map"
+        );
+    }
+
+    #[test]
+    fn map_methods() {
+        let program = "var m = {a: 1, b: 2}; var removed = m.remove(\"a\"); m[\"c\"] = 3;";
+        assert_eq!(
+            eval(
+                program,
+                "[m.keys(), m.values(), m.entries(), m.len(), removed]"
+            )
+            .unwrap(),
+            "[[\"b\", \"c\"], [2, 3], [(\"b\", 2), (\"c\", 3)], 2, 1]"
+        );
+        assert_eq!(
+            eval(
+                program,
+                "[m.has(\"b\"), m.has(\"a\"), m.get(\"a\"), m.get(\"a\", 0)]"
+            )
+            .unwrap(),
+            "[true, false, nil, 0]"
+        );
+        assert_eq!(
+            failure("var m = {}; m.remove(1);"),
+            "Key 1 not found in map"
+        );
+    }
+
+    #[test]
+    fn iterating_over_a_map_gives_its_keys_in_order() {
+        let program = "var m = {z: 1, a: 2}; var keys = []; for (k in m) keys.push(k);";
+        assert_eq!(eval(program, "keys").unwrap(), "[\"z\", \"a\"]");
+    }
+
+    #[test]
+    fn tuples_are_made_by_a_comma_in_parentheses() {
+        assert_eq!(
+            value("[(1), (1,), (), (1, \"a\")]"),
+            "[1, (1,), (), (1, \"a\")]"
+        );
+        assert_eq!(value("(1, 2)[1]"), "2");
+        assert_eq!(
+            failure("var t = (1, 2); t[0] = 3;"),
+            "Can't assign to an element of a tuple"
+        );
+    }
+
+    #[test]
+    fn declarations_assignments_and_parameters_destructure() {
+        let program = "var (x, y) = (1, 2); var [head, ...rest] = [1, 2, 3];
+            var {name, age: years} = {name: \"Ada\", age: 36};
+            fun swap((a, b)) { return (b, a); }
+            var (p, q) = (0, 0); (p, q) = swap((x, y));";
+        assert_eq!(
+            eval(program, "[x, y, head, rest, name, years, p, q]").unwrap(),
+            "[1, 2, 1, [2, 3], \"Ada\", 36, 2, 1]"
+        );
+        let program = "var xs = [0, 0]; var m = {}; [xs[0], ...m[\"rest\"]] = [1, 2, 3];";
+        assert_eq!(
+            eval(program, "[xs, m]").unwrap(),
+            "[[1, 0], {\"rest\": [2, 3]}]"
+        );
+    }
+
+    #[test]
+    fn destructuring_the_wrong_shape_is_an_error() {
+        assert_eq!(
+            failure("var (a, b) = (1, 2, 3);"),
+            "Expected 2 values to unpack, got 3"
+        );
+        assert_eq!(
+            failure("var a; var b; [a, b] = 1;"),
+            "Can only unpack a tuple or list, not number"
+        );
+    }
+
+    /// The items that `for (x in <iterable>)` visits, after running `program`.
+    fn items(program: &str, iterable: &str) -> Result<String, Error> {
+        let program = format!(
+            "{} var items = []; for (x in {}) items.push(x);",
+            program, iterable
+        );
+        eval(&program, "items")
+    }
+
+    #[test]
+    fn for_in_visits_every_item() {
+        assert_eq!(items("", "[1, 2]").unwrap(), "[1, 2]");
+        assert_eq!(items("", "(1, nil)").unwrap(), "[1, nil]");
+        assert_eq!(items("", "\"hé\"").unwrap(), "[\"h\", \"é\"]");
+        assert_eq!(items("", "range(3)").unwrap(), "[0, 1, 2]");
+        assert_eq!(items("", "range(5, 0, -2)").unwrap(), "[5, 3, 1]");
+        assert_eq!(items("", "range(0)").unwrap(), "[]");
+        // Elements pushed during the loop are visited too
+        let program = "var xs = [1]; for (x in xs) if (x < 3) xs.push(x + 1);";
+        assert_eq!(eval(program, "xs").unwrap(), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn every_iteration_has_its_own_scope() {
+        let program = "var fs = []; for (i in range(3)) fs.push(lambda: i);";
+        assert_eq!(eval(program, "[fs[0](), fs[2]()]").unwrap(), "[0, 2]");
+    }
+
+    #[test]
+    fn a_function_iterator_returns_done_and_a_value() {
+        let program = "var i = 0; fun next() { i += 1; return (i > 3, i == 2 ? nil : i); }";
+        assert_eq!(items(program, "next").unwrap(), "[1, nil, 3]");
+        assert_eq!(
+            failure("for (x in lambda: nil) print x;"),
+            "An iterator function must return a (done, value) tuple, not nil"
+        );
+    }
+
+    #[test]
+    fn only_collections_ranges_and_functions_are_iterable() {
+        assert_eq!(
+            failure("for (x in 1) print x;"),
+            "Can't iterate over number"
+        );
+        assert_eq!(
+            failure("range(1, 2, 0);"),
+            "Step of 'range' must not be zero"
+        );
+        assert_eq!(
+            failure("range(\"a\");"),
+            "Arguments to 'range' must be numbers, not string"
+        );
+    }
+
+    #[test]
+    fn break_and_continue_work_in_every_loop() {
+        let program = "var seen = [];
+            for (x in range(10)) { if (x == 1) continue; if (x == 3) break; seen.push(x); }
+            var i = 0; while (true) { i++; if (i % 2 == 1) continue; if (i > 4) break; seen.push(i); }";
+        assert_eq!(eval(program, "seen").unwrap(), "[0, 2, 2, 4]");
+    }
+
+    #[test]
+    fn continue_in_a_for_loop_still_runs_the_increment() {
+        let program = "var seen = []; for (var i = 0; i < 5; i++) { if (i % 2 == 0) continue; seen.push(i); }";
+        assert_eq!(eval(program, "seen").unwrap(), "[1, 3]");
+    }
+
+    #[test]
+    fn labeled_break_and_continue_apply_to_their_loop() {
+        let program = "var seen = [];
+            outer: for (var i = 0; i < 3; i++) {
+                for (j in range(3)) {
+                    if (j == 1) continue outer;
+                    if (i == 2) break outer;
+                    seen.push((i, j));
+                }
+                seen.push(\"never\");
+            }";
+        assert_eq!(eval(program, "seen").unwrap(), "[(0, 0), (1, 0)]");
+        let program = "var n = 0;
+            outer: while (true) { inner: while (true) { n++; if (n < 3) continue inner; break outer; } }";
+        assert_eq!(eval(program, "n").unwrap(), "3");
+    }
+
+    #[test]
+    fn match_takes_the_first_arm_that_matches() {
+        let program = "fun describe(x) { return match (x) {
+                0 => \"zero\",
+                (a, b) => a + b,
+                {name} => name,
+                n if n == -3 => \"minus three\",
+                1..=9 => \"digit\",
+                1 => \"one\",
+                _ => \"other\",
+            }; }";
+        assert_eq!(
+            eval(
+                program,
+                "[describe(0), describe((1, 2)), describe({name: \"n\"})]"
+            )
+            .unwrap(),
+            "[\"zero\", 3, \"n\"]"
+        );
+        assert_eq!(
+            eval(
+                program,
+                "[describe(-3), describe(1), describe(9), describe(10)]"
+            )
+            .unwrap(),
+            "[\"minus three\", \"digit\", \"digit\", \"other\"]"
+        );
+    }
+
+    #[test]
+    fn a_guard_sees_the_names_of_its_pattern_and_may_reject_the_arm() {
+        let program = "var calls = 0; fun big(n) { calls++; return n > 10; }
+            var r = match ([20, 5]) { [a, b] if big(b) => \"b\", [a, _] if big(a) => \"a\", _ => \"none\" };";
+        assert_eq!(eval(program, "[r, calls]").unwrap(), "[\"a\", 2]");
+    }
+
+    #[test]
+    fn no_matching_arm_is_an_error() {
+        assert_eq!(
+            failure("print match (\"x\") { 1 => 1, [a] => a };"),
+            "No match arm matches \"x\""
+        );
+    }
+
+    #[test]
+    fn a_conditional_evaluates_only_the_chosen_branch() {
+        let program = "var hits = 0; var x = true ? 1 : (hits = 1);";
+        assert_eq!(eval(program, "[x, hits]").unwrap(), "[1, 0]");
+        // Only `nil` and `false` are falsy
+        assert_eq!(value("nil ? 1 : 0 ? 2 : 3"), "2");
+    }
+
+    #[test]
+    fn the_comma_operator_evaluates_both_and_gives_the_right() {
+        assert_eq!(eval("var a = 0;", "a = 1, a + 1").unwrap(), "2");
+        // In parentheses a comma makes a tuple instead
+        assert_eq!(value("(1, 2)"), "(1, 2)");
+    }
+
+    #[test]
+    fn and_or_give_an_operand_and_short_circuit() {
+        assert_eq!(value("nil or \"default\""), "default");
+        assert_eq!(value("1 or 2"), "1");
+        assert_eq!(value("1 and 2"), "2");
+        assert_eq!(value("false and 2"), "false");
+        let program = "var hits = 0; true or (hits = 1); false and (hits = 2);";
+        assert_eq!(eval(program, "hits").unwrap(), "0");
+    }
+
+    #[test]
+    fn errors_name_the_operand_types() {
+        assert_eq!(
+            failure("print 1 + true;"),
+            "Operands of '+' must be two numbers or two strings, not number and bool"
+        );
+        assert!(failure("print -\"a\";").contains("string"));
+        assert!(failure("print undefined;").contains("undefined"));
+    }
+}
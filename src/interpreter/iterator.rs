@@ -0,0 +1,89 @@
+use crate::interpreter::value::{Range, Value};
+use crate::interpreter::Interpreter;
+use crate::parser::{Ast, ExprId};
+use crate::{Error, ErrorKind};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How far a `for ... in` loop has got through the value it iterates over.
+///
+/// Lists yield their elements, tuples their items, maps their keys, strings their
+/// characters and ranges their numbers. A function is an iterator of its own: it is called
+/// without arguments for every item and returns a `(done, value)` tuple. The loop ends
+/// when `done` is truthy, so an iterator can yield `nil` like any other value.
+///
+/// This is not yet the protocol that user classes are meant to get, with an `iter()`
+/// method that returns an object with a `next()` method. Seid has no classes, so a function
+/// stands in for `next()` until it does.
+pub(crate) enum Iter {
+    /// A list is read at every step, so elements pushed inside the loop are visited too.
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    /// Items taken when the loop started, e.g. the keys of a map.
+    Items(std::vec::IntoIter<Value>),
+    Range(Range, usize),
+    Function(Value),
+}
+
+impl Iter {
+    pub(crate) fn new(value: Value) -> Result<Iter, String> {
+        let iter = match value {
+            Value::List(values) => Iter::List(values, 0),
+            Value::Tuple(values) => Iter::Items(Vec::from(&values[..]).into_iter()),
+            Value::Map(map) => Iter::Items(
+                map.borrow()
+                    .entries()
+                    .iter()
+                    .map(|(key, _)| key.clone())
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ),
+            Value::Str(s) => Iter::Items(
+                s.chars()
+                    .map(|c| Value::Str(Rc::from(c.to_string())))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ),
+            Value::Range(range) => Iter::Range(range, 0),
+            Value::Function(_) | Value::Method(_) | Value::Native(_) => Iter::Function(value),
+            _ => return Err(format!("Can't iterate over {}", value.type_name())),
+        };
+        Ok(iter)
+    }
+}
+
+impl Interpreter {
+    /// The next item of `iter`, or `None` when it is done. `id` is the iterated expression.
+    pub(crate) fn next(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        iter: &mut Iter,
+    ) -> Result<Option<Value>, Error> {
+        let item = match iter {
+            Iter::List(values, i) => {
+                let item = values.borrow().get(*i).cloned();
+                *i += 1;
+                item
+            }
+            Iter::Items(items) => items.next(),
+            Iter::Range(range, i) => {
+                let item = range.get(*i).map(Value::Number);
+                *i += 1;
+                item
+            }
+            Iter::Function(function) => match self.call(ast, id, function.clone(), Vec::new())? {
+                Value::Tuple(signal) if signal.len() == 2 => {
+                    (!signal[0].is_truthy()).then(|| signal[1].clone())
+                }
+                other => {
+                    let message = format!(
+                        "An iterator function must return a (done, value) tuple, not {}",
+                        other.repr()
+                    );
+                    return Err(self.error_at(ast, id, ErrorKind::Type, &message));
+                }
+            },
+        };
+        Ok(item)
+    }
+}
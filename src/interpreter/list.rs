@@ -0,0 +1,197 @@
+use crate::interpreter::interpreter::{describe, integer, runtime_error, type_error, Failure};
+use crate::interpreter::value::Value;
+use crate::interpreter::Interpreter;
+use crate::parser::{Ast, ExprId};
+use crate::{Error, ErrorKind, Symbol};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const METHODS: &[&str] = &[
+    "push", "pop", "len", "insert", "remove", "contains", "sort", "reverse", "map", "filter",
+    "reduce",
+];
+
+pub(crate) fn has_method(name: Symbol) -> bool {
+    METHODS.contains(&name.as_str())
+}
+
+/// Resolves an index into a list or tuple of the given length, `kind` says which one.
+/// Negative indices count from the end.
+pub(crate) fn index(kind: &str, len: usize, index: &Value) -> Result<usize, Failure> {
+    let i = integer(index).ok_or_else(|| {
+        type_error(format!(
+            "{} index must be an integer, not {}",
+            kind,
+            describe(index)
+        ))
+    })?;
+    let resolved = if i < 0 { i + len as i64 } else { i };
+    if resolved < 0 || resolved >= len as i64 {
+        let message = format!(
+            "Index {} out of range for {} of length {}",
+            i,
+            kind.to_lowercase(),
+            len
+        );
+        return Err((ErrorKind::Index, message));
+    }
+    Ok(resolved as usize)
+}
+
+/// `values[start:end]` of a list or tuple. Bounds may be negative and are clamped to the
+/// values, so a slice is never out of range.
+pub(crate) fn slice(
+    values: &[Value],
+    start: Option<Value>,
+    end: Option<Value>,
+) -> Result<Vec<Value>, String> {
+    let len = values.len() as i64;
+    let bound = |value: Option<Value>, default: i64| -> Result<i64, String> {
+        match value {
+            None => Ok(default),
+            Some(value) => {
+                let i = integer(&value).ok_or_else(|| {
+                    format!("Slice bounds must be integers, not {}", describe(&value))
+                })?;
+                let i = if i < 0 { i + len } else { i };
+                Ok(i.clamp(0, len))
+            }
+        }
+    };
+    let start = bound(start, 0)?;
+    let end = bound(end, len)?;
+    if start < end {
+        Ok(values[start as usize..end as usize].to_vec())
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+impl Interpreter {
+    /// Calls the built-in list method `name`. `id` is the call expression.
+    pub(crate) fn list_method(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        list: &Rc<RefCell<Vec<Value>>>,
+        name: Symbol,
+        arguments: Vec<Value>,
+    ) -> Result<Value, Error> {
+        let error = |(kind, message): Failure| runtime_error(ast, id, kind, &message);
+        let value = match (name.as_str(), &arguments[..]) {
+            ("push", [value]) => {
+                list.borrow_mut().push(value.clone());
+                Value::Nil
+            }
+            ("pop", []) => list.borrow_mut().pop().ok_or_else(|| {
+                error((
+                    ErrorKind::Index,
+                    String::from("Can't pop from an empty list"),
+                ))
+            })?,
+            ("len", []) => Value::Number(list.borrow().len() as f64),
+            ("insert", [i, value]) => {
+                let mut values = list.borrow_mut();
+                // Inserting at the length appends
+                let i = if integer(i) == Some(values.len() as i64) {
+                    values.len()
+                } else {
+                    index("List", values.len(), i).map_err(error)?
+                };
+                values.insert(i, value.clone());
+                Value::Nil
+            }
+            ("remove", [i]) => {
+                let mut values = list.borrow_mut();
+                let i = index("List", values.len(), i).map_err(error)?;
+                values.remove(i)
+            }
+            ("contains", [value]) => Value::Bool(list.borrow().contains(value)),
+            ("sort", []) => {
+                let sorted =
+                    sorted(&list.borrow()).map_err(|message| error(type_error(message)))?;
+                *list.borrow_mut() = sorted;
+                Value::Nil
+            }
+            ("reverse", []) => {
+                list.borrow_mut().reverse();
+                Value::Nil
+            }
+            // The callbacks may change the list, so they run over a copy
+            ("map", [function]) => {
+                let values = list.borrow().clone();
+                let mut mapped = Vec::with_capacity(values.len());
+                for value in values {
+                    mapped.push(self.call(ast, id, function.clone(), vec![value])?);
+                }
+                Value::list(mapped)
+            }
+            ("filter", [function]) => {
+                let values = list.borrow().clone();
+                let mut kept = Vec::new();
+                for value in values {
+                    if self
+                        .call(ast, id, function.clone(), vec![value.clone()])?
+                        .is_truthy()
+                    {
+                        kept.push(value);
+                    }
+                }
+                Value::list(kept)
+            }
+            ("reduce", [function, rest @ ..]) if rest.len() <= 1 => {
+                let mut values = list.borrow().clone().into_iter();
+                let mut accumulator = match rest.first() {
+                    Some(initial) => initial.clone(),
+                    None => values.next().ok_or_else(|| {
+                        error((
+                            ErrorKind::Value,
+                            String::from("Can't reduce an empty list without an initial value"),
+                        ))
+                    })?,
+                };
+                for value in values {
+                    accumulator = self.call(ast, id, function.clone(), vec![accumulator, value])?;
+                }
+                accumulator
+            }
+            _ => {
+                return Err(error(type_error(format!(
+                    "Wrong number of arguments to list method '{}': {}",
+                    name,
+                    arguments.len()
+                ))))
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// Lists of numbers and lists of strings sort, anything else is an error.
+fn sorted(values: &[Value]) -> Result<Vec<Value>, String> {
+    let numbers: Option<Vec<f64>> = values
+        .iter()
+        .map(|value| match value {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        })
+        .collect();
+    if let Some(mut numbers) = numbers {
+        numbers.sort_by(f64::total_cmp);
+        return Ok(numbers.into_iter().map(Value::Number).collect());
+    }
+    let strings: Option<Vec<Rc<str>>> = values
+        .iter()
+        .map(|value| match value {
+            Value::Str(s) => Some(Rc::clone(s)),
+            _ => None,
+        })
+        .collect();
+    if let Some(mut strings) = strings {
+        strings.sort();
+        return Ok(strings.into_iter().map(Value::Str).collect());
+    }
+    Err(String::from(
+        "Can only sort a list of numbers or a list of strings",
+    ))
+}
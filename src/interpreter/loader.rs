@@ -0,0 +1,364 @@
+use crate::parser::{Ast, Parser, SideTable, StmtId, StmtKind};
+use crate::{Error, Scanner, Symbol};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Where imports look for a module that isn't next to the importing file.
+#[derive(Debug, Clone, Default)]
+pub struct SearchPath {
+    /// Directories to look in, in order.
+    pub roots: Vec<PathBuf>,
+    /// Packages by name. An import path that starts with the name of a package is looked up
+    /// in the package's roots, e.g. `"util/strings"`, and the bare name imports its entry.
+    pub packages: HashMap<String, Package>,
+}
+
+impl SearchPath {
+    /// The directories listed in `SEID_PATH`.
+    pub fn from_env() -> Self {
+        let roots = std::env::var_os("SEID_PATH")
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        SearchPath {
+            roots,
+            packages: HashMap::new(),
+        }
+    }
+}
+
+/// A package that imports can name, see `SearchPath`.
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub entry: PathBuf,
+    pub roots: Vec<PathBuf>,
+}
+
+/// A file that a program imports, parsed into the program's `Ast`.
+#[derive(Debug)]
+pub(crate) struct Module {
+    pub(crate) path: Rc<str>,
+    pub(crate) statements: Vec<StmtId>,
+    /// The names its top-level `export`s declare.
+    pub(crate) exports: Vec<Symbol>,
+}
+
+/// Finds and parses the files that imports name, before any code runs, so that a missing
+/// file or an import cycle is reported up front. Each file is parsed once, however many
+/// imports name it.
+#[derive(Debug)]
+pub(crate) struct Loader {
+    search_path: SearchPath,
+    pub(crate) modules: Vec<Module>,
+    // Indices into `modules` by canonical path
+    loaded: HashMap<PathBuf, usize>,
+    // Index into `modules` of what each import statement imports
+    pub(crate) imports: SideTable<StmtId, usize>,
+    // The files whose imports are being loaded, the importing one first
+    loading: Vec<(PathBuf, String)>,
+    warnings: Vec<String>,
+}
+
+impl Loader {
+    pub(crate) fn new(search_path: SearchPath) -> Self {
+        Loader {
+            search_path,
+            modules: Vec::new(),
+            loaded: HashMap::new(),
+            imports: SideTable::new(),
+            loading: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Loads what `statements` import, and what that imports in turn. `file` is where the
+    /// statements come from, or `None` for the REPL, whose imports are relative to the
+    /// current directory. Returns the warnings from parsing the new modules.
+    pub(crate) fn load(
+        &mut self,
+        ast: &mut Ast,
+        statements: &[StmtId],
+        file: Option<&Path>,
+    ) -> Result<Vec<String>, Error> {
+        if let Some(file) = file {
+            let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+            self.loading.push((canonical, file.display().to_string()));
+        }
+        let directory = file
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf();
+        let result = self.load_imports(ast, statements, &directory);
+        self.loading.clear();
+        result?;
+        Ok(std::mem::take(&mut self.warnings))
+    }
+
+    fn load_imports(
+        &mut self,
+        ast: &mut Ast,
+        statements: &[StmtId],
+        directory: &Path,
+    ) -> Result<(), Error> {
+        for &id in statements {
+            let (path, names) = match &ast[id].kind {
+                StmtKind::Import(path, _) => (Rc::clone(path), Vec::new()),
+                StmtKind::FromImport(path, names) => (Rc::clone(path), names.clone()),
+                _ => continue,
+            };
+            let module = self.load_module(ast, id, &path, directory)?;
+            let exports = &self.modules[module].exports;
+            if let Some(name) = names.iter().find(|name| !exports.contains(name)) {
+                let message = format!(
+                    "Module '{}' does not export '{}'",
+                    self.modules[module].path, name
+                );
+                return Err(self.error(ast, id, message));
+            }
+            self.imports.insert(id, module);
+        }
+        Ok(())
+    }
+
+    /// The index of the module that the import `id` names with `path`, loading it first
+    /// if this is the first import of it.
+    fn load_module(
+        &mut self,
+        ast: &mut Ast,
+        id: StmtId,
+        path: &str,
+        directory: &Path,
+    ) -> Result<usize, Error> {
+        let file = match self.find(path, directory) {
+            Some(file) => file,
+            None => {
+                let message = format!("Can't find module '{}'", path);
+                return Err(self.error(ast, id, message));
+            }
+        };
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        let name = file.display().to_string();
+        if let Some(start) = self.loading.iter().position(|(open, _)| *open == canonical) {
+            let mut cycle: Vec<&str> = self.loading[start..]
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .collect();
+            cycle.push(&name);
+            let message = format!("Import cycle: {}", cycle.join(" -> "));
+            return Err(self.error(ast, id, message));
+        }
+        if let Some(&module) = self.loaded.get(&canonical) {
+            return Ok(module);
+        }
+
+        let source = match std::fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(e) => {
+                let message = format!("Can't read module '{}': {}", name, e);
+                return Err(self.error(ast, id, message));
+            }
+        };
+        let parsed = Parser::new(Scanner::new(&source), ast).and_then(|mut parser| {
+            let statements = parser.parse()?;
+            for warning in parser.warnings() {
+                self.warnings
+                    .push(format!("In module '{}': {}", name, warning));
+            }
+            Ok(statements)
+        });
+        let statements = match parsed {
+            Ok(statements) => statements,
+            Err(e) => {
+                let message = format!("In module '{}': {}", name, e);
+                return Err(self.error(ast, id, message));
+            }
+        };
+
+        self.loading.push((canonical.clone(), name.clone()));
+        let directory = file.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        let result = self.load_imports(ast, &statements, &directory);
+        self.loading.pop();
+        result?;
+
+        let exports = statements
+            .iter()
+            .filter_map(|&stmt| match &ast[stmt].kind {
+                StmtKind::Export(declaration) => Some(*declaration),
+                _ => None,
+            })
+            .flat_map(|declaration| match &ast[declaration].kind {
+                StmtKind::Var(pattern, _) => pattern.names(),
+                StmtKind::Function(name, _, _) => vec![*name],
+                _ => Vec::new(),
+            })
+            .collect();
+        self.modules.push(Module {
+            path: Rc::from(name),
+            statements,
+            exports,
+        });
+        self.loaded.insert(canonical, self.modules.len() - 1);
+        Ok(self.modules.len() - 1)
+    }
+
+    /// The file that `import` names: next to the importing file, or else in the package it
+    /// starts with, or else in the first root of the search path that has it.
+    fn find(&self, import: &str, directory: &Path) -> Option<PathBuf> {
+        let path = source_file(Path::new(import));
+        if path.is_absolute() {
+            return path.is_file().then_some(path);
+        }
+        let mut candidates = vec![directory.join(&path)];
+        let mut components = Path::new(import).components();
+        let package = components
+            .next()
+            .and_then(|name| name.as_os_str().to_str())
+            .and_then(|name| self.search_path.packages.get(name));
+        if let Some(package) = package {
+            let rest = components.as_path();
+            if rest.as_os_str().is_empty() {
+                candidates.push(package.entry.clone());
+            } else {
+                let rest = source_file(rest);
+                candidates.extend(package.roots.iter().map(|root| root.join(&rest)));
+            }
+        }
+        candidates.extend(self.search_path.roots.iter().map(|root| root.join(&path)));
+        candidates.into_iter().find(|file| file.is_file())
+    }
+
+    fn error(&self, ast: &Ast, id: StmtId, message: String) -> Error {
+        let message = match self.loading.last() {
+            Some((_, importer)) => format!("{} (in '{}')", message, importer),
+            None => message,
+        };
+        Error::ImportError(format!("{}", ast[id].span.line), message)
+    }
+}
+
+/// A path without an extension names a `.sd` file.
+fn source_file(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    if path.extension().is_none() {
+        path.set_extension("sd");
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::testing::Files;
+
+    /// Runs the program in `main` with its imports, then gives the value of `expression`.
+    fn run(main: &Path, search_path: SearchPath, expression: &str) -> Result<String, Error> {
+        let source = std::fs::read_to_string(main).unwrap();
+        let mut ast = Ast::new();
+        let mut interpreter = Interpreter::with_search_path(search_path);
+        let statements = Parser::new(Scanner::new(&source), &mut ast)?.parse()?;
+        interpreter.load(&mut ast, &statements, Some(main))?;
+        for id in statements {
+            interpreter.execute(&ast, id)?;
+        }
+        let source = format!("{};", expression);
+        let statements = Parser::new(Scanner::new(&source), &mut ast)?.parse()?;
+        let StmtKind::Expression(id) = ast[statements[0]].kind else {
+            panic!("`{}` is not an expression", expression);
+        };
+        interpreter
+            .evaluate(&ast, id)
+            .map(|value| value.to_string())
+    }
+
+    #[test]
+    fn imports_are_relative_to_the_importing_file() {
+        let files = Files::new(&[
+            ("main.sd", "from \"lib/a\" import x;"),
+            ("lib/a.sd", "from \"b\" import y;\nexport var x = y + 1;"),
+            ("lib/b.sd", "export var y = 1;"),
+            // Not next to `lib/a.sd`, so not what it imports
+            ("b.sd", "export var y = 100;"),
+        ]);
+        let value = run(&files.path("main.sd"), SearchPath::default(), "x");
+        assert_eq!(value.unwrap(), "2");
+    }
+
+    #[test]
+    fn seid_path_roots_are_searched_in_order_after_the_importing_directory() {
+        let files = Files::new(&[
+            (
+                "app/main.sd",
+                "from \"util\" import v;\nfrom \"local\" import w;",
+            ),
+            ("app/local.sd", "export var w = \"app\";"),
+            ("first/util.sd", "export var v = \"first\";"),
+            ("second/util.sd", "export var v = \"second\";"),
+            ("second/local.sd", "export var w = \"second\";"),
+        ]);
+        let paths = [files.path("first"), files.path("second")];
+        std::env::set_var("SEID_PATH", std::env::join_paths(&paths).unwrap());
+        let search_path = SearchPath::from_env();
+        std::env::remove_var("SEID_PATH");
+        assert_eq!(search_path.roots, paths);
+
+        let main = files.path("app/main.sd");
+        assert_eq!(run(&main, search_path.clone(), "v").unwrap(), "first");
+        assert_eq!(run(&main, search_path, "w").unwrap(), "app");
+    }
+
+    #[test]
+    fn every_module_runs_once() {
+        let files = Files::new(&[
+            (
+                "main.sd",
+                "import \"a\" as a;\nimport \"b\" as b;\nfrom \"shared\" import log;",
+            ),
+            ("a.sd", "from \"shared\" import log;\nlog.push(\"a\");"),
+            ("b.sd", "from \"shared\" import log;\nlog.push(\"b\");"),
+            ("shared.sd", "export var log = [];"),
+        ]);
+        // A second run of `shared` would have given `b` a new list
+        let value = run(&files.path("main.sd"), SearchPath::default(), "log");
+        assert_eq!(value.unwrap(), "[\"a\", \"b\"]");
+    }
+
+    #[test]
+    fn an_import_cycle_is_an_error() {
+        let files = Files::new(&[
+            ("main.sd", "import \"a\" as a;"),
+            ("a.sd", "import \"b\" as b;"),
+            ("b.sd", "\nimport \"a\" as a;"),
+        ]);
+        let (a, b) = (files.path("a.sd"), files.path("b.sd"));
+        match run(&files.path("main.sd"), SearchPath::default(), "nil") {
+            Err(Error::ImportError(line, message)) => {
+                assert_eq!(line, "2");
+                assert_eq!(
+                    message,
+                    format!(
+                        "Import cycle: {a} -> {b} -> {a} (in '{b}')",
+                        a = a.display(),
+                        b = b.display()
+                    )
+                );
+            }
+            other => panic!("expected an import cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_missing_module_is_an_error() {
+        let files = Files::new(&[("main.sd", "import \"nowhere\" as n;")]);
+        match run(&files.path("main.sd"), SearchPath::default(), "nil") {
+            Err(Error::ImportError(_, message)) => assert_eq!(
+                message,
+                format!(
+                    "Can't find module 'nowhere' (in '{}')",
+                    files.path("main.sd").display()
+                )
+            ),
+            other => panic!("expected a missing module, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use crate::interpreter::interpreter::runtime_error;
+use crate::interpreter::value::Value;
+use crate::interpreter::Interpreter;
+use crate::parser::{Ast, ExprId};
+use crate::{Error, ErrorKind, Symbol};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+const METHODS: &[&str] = &["keys", "values", "entries", "has", "get", "remove", "len"];
+
+pub(crate) fn has_method(name: Symbol) -> bool {
+    METHODS.contains(&name.as_str())
+}
+
+/// A value used as a map key.
+///
+/// Nil, booleans, numbers and strings are keys by value, like `==` compares them, except
+/// that NaN is equal to itself and `-0` to `0`, so that every key can be found again.
+/// Tuples and ranges can't change, so they are keys by value too. Lists, maps and functions
+/// are keys by identity: a list can change after it was used as a key, its identity can't.
+#[derive(Debug, Clone)]
+pub struct Key(pub Value);
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Value::Number(a), Value::Number(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Value::Tuple(a), Value::Tuple(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|(a, b)| Key(a.clone()) == Key(b.clone()))
+            }
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Error(a), Value::Error(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
+            (Value::Range(a), Value::Range(b)) => {
+                Key(Value::Number(a.start)) == Key(Value::Number(b.start))
+                    && Key(Value::Number(a.end)) == Key(Value::Number(b.end))
+                    && Key(Value::Number(a.step)) == Key(Value::Number(b.step))
+            }
+            (Value::Method(a), Value::Method(b)) => {
+                a.name == b.name && Key(a.receiver.clone()) == Key(b.receiver.clone())
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&self.0).hash(state);
+        match &self.0 {
+            Value::Nil => {}
+            Value::Bool(b) => b.hash(state),
+            Value::Number(n) => {
+                let n = if n.is_nan() {
+                    f64::NAN
+                } else if *n == 0.0 {
+                    0.0
+                } else {
+                    *n
+                };
+                n.to_bits().hash(state)
+            }
+            Value::Str(s) => s.hash(state),
+            Value::Tuple(values) => {
+                for value in values.iter() {
+                    Key(value.clone()).hash(state);
+                }
+            }
+            Value::List(values) => Rc::as_ptr(values).hash(state),
+            Value::Map(map) => Rc::as_ptr(map).hash(state),
+            Value::Function(function) => Rc::as_ptr(function).hash(state),
+            Value::Native(native) => Rc::as_ptr(native).hash(state),
+            Value::Error(error) => Rc::as_ptr(error).hash(state),
+            Value::Module(module) => Rc::as_ptr(module).hash(state),
+            Value::Range(range) => {
+                for n in [range.start, range.end, range.step] {
+                    Key(Value::Number(n)).hash(state);
+                }
+            }
+            Value::Method(method) => {
+                method.name.as_str().hash(state);
+                Key(method.receiver.clone()).hash(state);
+            }
+        }
+    }
+}
+
+/// A hash map that remembers the order in which keys were first inserted, which is the
+/// order it is printed and iterated in.
+#[derive(Debug, Default)]
+pub struct Map {
+    entries: Vec<(Value, Value)>,
+    // Position of every key in `entries`
+    positions: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Map::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[(Value, Value)] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        let position = self.positions.get(&Key(key.clone()))?;
+        Some(&self.entries[*position].1)
+    }
+
+    /// Sets the value of a key. A key that is already there keeps its position.
+    pub fn insert(&mut self, key: Value, value: Value) {
+        match self.positions.get(&Key(key.clone())) {
+            Some(position) => self.entries[*position].1 = value,
+            None => {
+                self.positions.insert(Key(key.clone()), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        let position = self.positions.remove(&Key(key.clone()))?;
+        let (_, value) = self.entries.remove(position);
+        for moved in self.positions.values_mut() {
+            if *moved > position {
+                *moved -= 1;
+            }
+        }
+        Some(value)
+    }
+}
+
+pub(crate) fn missing_key(key: &Value) -> String {
+    format!("Key {} not found in map", key.repr())
+}
+
+impl Interpreter {
+    /// Calls the built-in map method `name`. `id` is the call expression.
+    pub(crate) fn map_method(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        map: &Rc<RefCell<Map>>,
+        name: Symbol,
+        arguments: Vec<Value>,
+    ) -> Result<Value, Error> {
+        let value = match (name.as_str(), &arguments[..]) {
+            ("keys", []) => Value::list(
+                map.borrow()
+                    .entries()
+                    .iter()
+                    .map(|(key, _)| key.clone())
+                    .collect(),
+            ),
+            ("values", []) => Value::list(
+                map.borrow()
+                    .entries()
+                    .iter()
+                    .map(|(_, value)| value.clone())
+                    .collect(),
+            ),
+            // Every entry as a `(key, value)` tuple
+            ("entries", []) => Value::list(
+                map.borrow()
+                    .entries()
+                    .iter()
+                    .map(|(key, value)| Value::tuple(vec![key.clone(), value.clone()]))
+                    .collect(),
+            ),
+            ("has", [key]) => Value::Bool(map.borrow().get(key).is_some()),
+            ("get", [key, rest @ ..]) if rest.len() <= 1 => match map.borrow().get(key) {
+                Some(value) => value.clone(),
+                None => rest.first().cloned().unwrap_or(Value::Nil),
+            },
+            ("remove", [key]) => map
+                .borrow_mut()
+                .remove(key)
+                .ok_or_else(|| runtime_error(ast, id, ErrorKind::Key, &missing_key(key)))?,
+            ("len", []) => Value::Number(map.borrow().len() as f64),
+            _ => {
+                return Err(runtime_error(
+                    ast,
+                    id,
+                    ErrorKind::Type,
+                    &format!(
+                        "Wrong number of arguments to map method '{}': {}",
+                        name,
+                        arguments.len()
+                    ),
+                ))
+            }
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(n: f64) -> Value {
+        Value::Number(n)
+    }
+
+    #[test]
+    fn nan_and_negative_zero_keys_are_found_again() {
+        let mut map = Map::new();
+        map.insert(number(f64::NAN), number(1.0));
+        map.insert(number(-0.0), number(2.0));
+        assert_eq!(map.get(&number(f64::NAN)), Some(&number(1.0)));
+        assert_eq!(map.get(&number(0.0)), Some(&number(2.0)));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn lists_are_keys_by_identity_and_tuples_by_value() {
+        let mut map = Map::new();
+        let list = Value::list(vec![number(1.0)]);
+        map.insert(list.clone(), number(1.0));
+        map.insert(Value::tuple(vec![number(1.0)]), number(2.0));
+        assert_eq!(map.get(&list), Some(&number(1.0)));
+        assert_eq!(map.get(&Value::list(vec![number(1.0)])), None);
+        assert_eq!(
+            map.get(&Value::tuple(vec![number(1.0)])),
+            Some(&number(2.0))
+        );
+        // A key that changes can still be found
+        if let Value::List(values) = &list {
+            values.borrow_mut().push(Value::Nil);
+        }
+        assert_eq!(map.get(&list), Some(&number(1.0)));
+    }
+
+    #[test]
+    fn keys_keep_the_order_they_were_first_inserted_in() {
+        let mut map = Map::new();
+        for (key, value) in [("b", 1.0), ("a", 2.0), ("c", 3.0), ("b", 4.0)] {
+            map.insert(Value::Str(Rc::from(key)), number(value));
+        }
+        assert_eq!(map.remove(&Value::Str(Rc::from("a"))), Some(number(2.0)));
+        assert_eq!(map.remove(&Value::Str(Rc::from("a"))), None);
+        let entries: Vec<String> = map
+            .entries()
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        assert_eq!(entries, ["b=4", "c=3"]);
+        // Positions after the removed key moved up
+        assert_eq!(map.get(&Value::Str(Rc::from("c"))), Some(&number(3.0)));
+    }
+}
@@ -0,0 +1,18 @@
+mod destructure;
+mod environment;
+mod exception;
+#[allow(clippy::module_inception)]
+mod interpreter;
+mod iterator;
+mod list;
+mod loader;
+mod map;
+mod natives;
+mod value;
+
+pub use environment::Environment;
+pub use exception::{stack_trace, Exception, StackFrame};
+pub use interpreter::Interpreter;
+pub use loader::{Package, SearchPath};
+pub use map::{Key, Map};
+pub use value::{Body, Exports, Function, Method, Native, Range, Value};
@@ -0,0 +1,52 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::interpreter::{type_error, Failure};
+use crate::interpreter::value::{Native, Range, Value};
+use crate::{ErrorKind, Symbol};
+use std::rc::Rc;
+
+const NATIVES: &[Native] = &[Native {
+    name: "range",
+    function: range,
+}];
+
+/// Declares the built-in functions in the global scope.
+pub(crate) fn define(environment: &mut Environment) {
+    for native in NATIVES {
+        let value = Value::Native(Rc::new(native.clone()));
+        environment.define(Symbol::intern(native.name), value);
+    }
+}
+
+/// `range(end)`, `range(start, end)` or `range(start, end, step)`.
+fn range(arguments: &[Value]) -> Result<Value, Failure> {
+    let mut numbers = Vec::with_capacity(arguments.len());
+    for argument in arguments {
+        match argument {
+            Value::Number(n) => numbers.push(*n),
+            _ => {
+                return Err(type_error(format!(
+                    "Arguments to 'range' must be numbers, not {}",
+                    argument.type_name()
+                )))
+            }
+        }
+    }
+    let (start, end, step) = match numbers[..] {
+        [end] => (0.0, end, 1.0),
+        [start, end] => (start, end, 1.0),
+        [start, end, step] => (start, end, step),
+        _ => {
+            return Err(type_error(format!(
+                "Expected 1 to 3 arguments to 'range' but got {}",
+                arguments.len()
+            )))
+        }
+    };
+    if step == 0.0 || step.is_nan() {
+        return Err((
+            ErrorKind::Value,
+            "Step of 'range' must not be zero".to_string(),
+        ));
+    }
+    Ok(Value::Range(Range { start, end, step }))
+}
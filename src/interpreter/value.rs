@@ -0,0 +1,400 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::exception::Exception;
+use crate::interpreter::interpreter::Failure;
+use crate::interpreter::map::Map;
+use crate::parser::{ExprId, LiteralOp, Pattern, StmtId};
+use crate::Symbol;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// A runtime value. Lists, tuples, maps and functions are shared by reference, and tuples
+/// can't be changed.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(Rc<str>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Tuple(Rc<[Value]>),
+    Map(Rc<RefCell<Map>>),
+    Function(Rc<Function>),
+    Method(Rc<Method>),
+    Native(Rc<Native>),
+    Range(Range),
+    Error(Rc<Exception>),
+    Module(Rc<Exports>),
+}
+
+impl Value {
+    pub fn list(values: Vec<Value>) -> Self {
+        Value::List(Rc::new(RefCell::new(values)))
+    }
+
+    pub fn tuple(values: Vec<Value>) -> Self {
+        Value::Tuple(Rc::from(values))
+    }
+
+    pub fn map(map: Map) -> Self {
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+
+    /// `nil` and `false` are falsy, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    /// The name of the value's type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Map(_) => "map",
+            Value::Function(_) => "function",
+            Value::Method(_) => "method",
+            Value::Native(_) => "function",
+            Value::Range(_) => "range",
+            Value::Error(_) => "error",
+            Value::Module(_) => "module",
+        }
+    }
+
+    /// The value as it is shown inside a list or map, where strings are quoted so that
+    /// `["1"]` doesn't look like `[1]`.
+    pub fn repr(&self) -> String {
+        match self {
+            Value::Str(s) => format!("{:?}", s),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl From<&LiteralOp> for Value {
+    fn from(literal: &LiteralOp) -> Self {
+        match literal {
+            LiteralOp::Number(n) => Value::Number(*n),
+            LiteralOp::Str(s) => Value::Str(Rc::clone(s)),
+            LiteralOp::True => Value::Bool(true),
+            LiteralOp::False => Value::Bool(false),
+            LiteralOp::Nil => Value::Nil,
+        }
+    }
+}
+
+/// Lists, tuples and maps are equal when their contents are, functions only to themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.equals(other, &mut Vec::new())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &mut Vec::new(), false)
+    }
+}
+
+/// The address of a list or map, which identifies it while it is being compared or printed.
+fn address<T>(rc: &Rc<T>) -> *const () {
+    Rc::as_ptr(rc) as *const ()
+}
+
+impl Value {
+    /// `visited` holds the lists and maps being compared further up. Reaching one of them
+    /// again means a cycle, like `xs.push(xs)`, and then only identity can decide.
+    fn equals(&self, other: &Value, visited: &mut Vec<*const ()>) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::List(a), Value::List(b)) => {
+                if Rc::ptr_eq(a, b) {
+                    return true;
+                }
+                let (a_address, b_address) = (address(a), address(b));
+                if visited.contains(&a_address) || visited.contains(&b_address) {
+                    return false;
+                }
+                visited.extend([a_address, b_address]);
+                let (a, b) = (a.borrow(), b.borrow());
+                let equal =
+                    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.equals(b, visited));
+                visited.truncate(visited.len() - 2);
+                equal
+            }
+            (Value::Tuple(a), Value::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.equals(b, visited))
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                if Rc::ptr_eq(a, b) {
+                    return true;
+                }
+                let (a_address, b_address) = (address(a), address(b));
+                if visited.contains(&a_address) || visited.contains(&b_address) {
+                    return false;
+                }
+                visited.extend([a_address, b_address]);
+                // The same keys with equal values, in any order
+                let (a, b) = (a.borrow(), b.borrow());
+                let equal = a.len() == b.len()
+                    && a.entries().iter().all(|(key, value)| {
+                        b.get(key).is_some_and(|other| value.equals(other, visited))
+                    });
+                visited.truncate(visited.len() - 2);
+                equal
+            }
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Method(a), Value::Method(b)) => {
+                a.name == b.name && a.receiver.equals(&b.receiver, visited)
+            }
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::Range(a), Value::Range(b)) => a == b,
+            (Value::Error(a), Value::Error(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Writes the value, quoting a string if it is inside a list, tuple or map. A list or
+    /// map that contains itself shows as `[...]` or `{...}` where it repeats.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter,
+        visited: &mut Vec<*const ()>,
+        quoted: bool,
+    ) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Str(s) if quoted => write!(f, "{:?}", s),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::List(values) => {
+                if visited.contains(&address(values)) {
+                    return write!(f, "[...]");
+                }
+                visited.push(address(values));
+                write!(f, "[")?;
+                for (i, value) in values.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.write(f, visited, true)?;
+                }
+                visited.pop();
+                write!(f, "]")
+            }
+            Value::Tuple(values) => {
+                write!(f, "(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.write(f, visited, true)?;
+                }
+                // `(1)` would be a number
+                if values.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Map(map) => {
+                if visited.contains(&address(map)) {
+                    return write!(f, "{{...}}");
+                }
+                visited.push(address(map));
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().entries().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.write(f, visited, true)?;
+                    write!(f, ": ")?;
+                    value.write(f, visited, true)?;
+                }
+                visited.pop();
+                write!(f, "}}")
+            }
+            Value::Function(function) => match function.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<lambda>"),
+            },
+            Value::Method(method) => write!(
+                f,
+                "<method {} of {}>",
+                method.name,
+                method.receiver.type_name()
+            ),
+            Value::Native(native) => write!(f, "<native fn {}>", native.name),
+            Value::Error(error) => write!(f, "{}: {}", error.kind, error.message),
+            Value::Module(module) => write!(f, "<module {}>", module.path),
+            Value::Range(range) => {
+                write!(f, "range({}, {}", range.start, range.end)?;
+                if range.step != 1.0 {
+                    write!(f, ", {}", range.step)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// A function declared with `fun`, or a lambda, together with the scope it closes over.
+#[derive(Debug)]
+pub struct Function {
+    pub name: Option<Symbol>,
+    pub params: Vec<Pattern>,
+    pub body: Body,
+    pub closure: Rc<RefCell<Environment>>,
+    /// The module it was declared in, or `None` for the main program.
+    pub module: Option<Rc<str>>,
+}
+
+#[derive(Debug)]
+pub enum Body {
+    Block(Vec<StmtId>),
+    /// A lambda's body, whose value is returned.
+    Expr(ExprId),
+}
+
+/// What `import ... as` binds a name to: the globals of a module, of which only the
+/// exported ones can be read.
+#[derive(Debug)]
+pub struct Exports {
+    pub path: Rc<str>,
+    pub names: Vec<Symbol>,
+    pub globals: Rc<RefCell<Environment>>,
+}
+
+impl Exports {
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        if !self.names.contains(&name) {
+            return None;
+        }
+        self.globals.borrow().get(name)
+    }
+}
+
+/// A built-in method bound to the value it was looked up on, e.g. `xs.push`.
+#[derive(Debug)]
+pub struct Method {
+    pub receiver: Value,
+    pub name: Symbol,
+}
+
+/// A function built into the interpreter, e.g. `range`. It checks its own arguments and
+/// says what kind of error a bad one is.
+#[derive(Clone)]
+pub struct Native {
+    pub name: &'static str,
+    pub function: fn(&[Value]) -> Result<Value, Failure>,
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+/// The numbers from `start` up to but not including `end`, `step` apart. A negative step
+/// counts down. The numbers are computed when they are iterated over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: f64,
+    pub end: f64,
+    pub step: f64,
+}
+
+impl Range {
+    pub fn len(&self) -> usize {
+        let steps = ((self.end - self.start) / self.step).ceil();
+        if steps > 0.0 {
+            steps as usize
+        } else {
+            0
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `i`th number, computed from `start` so that a fractional step doesn't add up
+    /// rounding errors.
+    pub fn get(&self, i: usize) -> Option<f64> {
+        (i < self.len()).then_some(self.start + i as f64 * self.step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `[1, <itself>]`
+    fn cycle() -> Value {
+        let xs = Value::list(vec![Value::Number(1.0)]);
+        if let Value::List(values) = &xs {
+            values.borrow_mut().push(xs.clone());
+        }
+        xs
+    }
+
+    #[test]
+    fn strings_are_quoted_inside_collections() {
+        let s = Value::Str(Rc::from("a"));
+        assert_eq!(s.to_string(), "a");
+        assert_eq!(Value::list(vec![s.clone()]).to_string(), "[\"a\"]");
+        assert_eq!(Value::tuple(vec![s]).to_string(), "(\"a\",)");
+    }
+
+    #[test]
+    fn a_list_that_contains_itself_prints_as_an_ellipsis() {
+        let xs = cycle();
+        assert_eq!(xs.to_string(), "[1, [...]]");
+        // Seen twice side by side, but not inside itself
+        let twice = Value::tuple(vec![xs.clone(), xs]);
+        assert_eq!(twice.to_string(), "([1, [...]], [1, [...]])");
+    }
+
+    #[test]
+    fn a_map_that_contains_itself_prints_as_an_ellipsis() {
+        let m = Value::map(Map::new());
+        if let Value::Map(map) = &m {
+            map.borrow_mut().insert(Value::Number(1.0), m.clone());
+        }
+        assert_eq!(m.to_string(), "{1: {...}}");
+    }
+
+    #[test]
+    fn cycles_compare_by_identity() {
+        // `assert_eq!` would debug print the cycle
+        let (xs, ys) = (cycle(), cycle());
+        assert!(xs == xs.clone());
+        assert!(xs != ys);
+        // Unrolling the cycle once reaches `xs` itself
+        let unrolled = Value::list(vec![Value::Number(1.0), xs.clone()]);
+        assert!(unrolled == xs);
+        assert!(unrolled != ys);
+    }
+
+    #[test]
+    fn collections_compare_by_contents() {
+        let a = Value::list(vec![Value::Number(1.0), Value::list(vec![Value::Nil])]);
+        let b = Value::list(vec![Value::Number(1.0), Value::list(vec![Value::Nil])]);
+        assert_eq!(a, b);
+        assert_ne!(a, Value::list(vec![Value::Number(1.0)]));
+        let mut first = Map::new();
+        first.insert(Value::Number(1.0), Value::Bool(true));
+        first.insert(Value::Number(2.0), a);
+        let mut second = Map::new();
+        second.insert(Value::Number(2.0), b);
+        second.insert(Value::Number(1.0), Value::Bool(true));
+        assert_eq!(Value::map(first), Value::map(second));
+    }
+}
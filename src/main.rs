@@ -1,17 +1,23 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::{fmt, path::PathBuf};
 mod error;
-pub use error::Error;
+pub use error::{Error, ErrorKind};
+pub mod formatter;
+mod interner;
+pub mod interpreter;
+pub use interner::Symbol;
+pub mod manifest;
 pub mod parser;
 pub mod scanner;
+#[cfg(test)]
+mod testing;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 pub use scanner::Scanner;
-pub use scanner::{Literal, Token, TokenType};
+pub use scanner::{Literal, Span, Token, TokenType, Trivia, TriviaKind};
 
 pub struct Repl {
-    history_path: String,
     readline: Editor<()>,
 }
 
@@ -21,34 +27,153 @@ impl Repl {
         let mut readline = Editor::<()>::new();
         // Attempt to read history if it exists
         let _ = readline.load_history(&history_path);
-        Repl {
-            history_path,
-            readline,
-        }
+        Repl { readline }
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[derive(Parser)]
 #[clap(about = "A runtime for mysterious bytes...")]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
     #[clap(parse(from_os_str), help = "Path to Seid file", default_value = "")]
     file_name: PathBuf,
     #[clap(short, long, help = "Arguments to the input file", default_value = "")]
     prog_args: Vec<String>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Reformat Seid files in the canonical style
+    Fmt {
+        #[clap(parse(from_os_str), required = true, help = "Seid files to format")]
+        files: Vec<PathBuf>,
+        #[clap(
+            long,
+            help = "Only report files that are not formatted, exiting with 1 if any"
+        )]
+        check: bool,
+    },
+    /// Run the project of the nearest seid.toml
+    Run {
+        #[clap(
+            long,
+            parse(from_os_str),
+            help = "Path to a seid.toml instead of the nearest one"
+        )]
+        manifest_path: Option<PathBuf>,
+        #[clap(long, help = "Fail instead of updating seid.lock")]
+        locked: bool,
+    },
+    /// Parse the project of the nearest seid.toml and its imports without running it
+    Check {
+        #[clap(
+            long,
+            parse(from_os_str),
+            help = "Path to a seid.toml instead of the nearest one"
+        )]
+        manifest_path: Option<PathBuf>,
+        #[clap(long, help = "Fail instead of updating seid.lock")]
+        locked: bool,
+    },
+}
+
 impl fmt::Display for Args {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "file name: {:?}", self.file_name)
     }
 }
 
+const INTERPRETER_STACK_SIZE: usize = 256 * 1024 * 1024;
+
 fn main() -> Result<(), anyhow::Error> {
     let args = Args::parse();
-    let mut seid = Seid::new(&args);
-    seid.start()?;
+    match &args.command {
+        Some(Command::Fmt { files, check }) => {
+            if !format_files(files, *check)? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Run {
+            manifest_path,
+            locked,
+        }) => {
+            let project = manifest::Project::find(manifest_path.as_deref())?;
+            project.update_lockfile(*locked)?;
+            let (entry, search_path) = (project.entry(), project.search_path());
+            let succeeded = run_interpreter(move || {
+                let mut seid = Seid::project(entry, search_path);
+                seid.start()?;
+                Ok(!seid.had_error)
+            })?;
+            if !succeeded {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Check {
+            manifest_path,
+            locked,
+        }) => {
+            let project = manifest::Project::find(manifest_path.as_deref())?;
+            project.update_lockfile(*locked)?;
+            if !Seid::project(project.entry(), project.search_path()).check() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        None => run_interpreter(move || Seid::new(&args).start()),
+    }
+}
 
-    Ok(())
+/// Runs `seid` on a thread of its own. Recursion in Seid code is recursion in the
+/// interpreter, which needs more stack than the main thread has.
+fn run_interpreter<T: Send + 'static>(
+    seid: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, anyhow::Error> {
+    let interpreter = std::thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(seid)?;
+    match interpreter.join() {
+        Ok(result) => Ok(result?),
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+/// Formats the files in place, or with `check` only reports the ones that would change.
+/// Returns whether all files were left formatted.
+fn format_files(files: &[PathBuf], check: bool) -> Result<bool, anyhow::Error> {
+    let mut formatted = true;
+    for file in files {
+        let source = std::fs::read_to_string(file)
+            .with_context(|| format!("could not read file `{}`", file.display()))?;
+        let output = match formatter::format_source(&source) {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                formatted = false;
+                continue;
+            }
+        };
+        if output == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", file.display());
+            formatted = false;
+        } else {
+            std::fs::write(file, output)
+                .with_context(|| format!("could not write file `{}`", file.display()))?;
+        }
+    }
+    Ok(formatted)
 }
 
 struct Seid {
@@ -56,6 +181,9 @@ struct Seid {
     repl: Repl,
     had_error: bool,
     use_prompt: bool,
+    docs: Vec<String>,
+    ast: parser::Ast,
+    interpreter: interpreter::Interpreter,
 }
 
 impl Seid {
@@ -67,11 +195,28 @@ impl Seid {
         } else {
             file_name = arg.file_name.clone();
         }
+        Seid::with_interpreter(file_name, run_prompt, interpreter::Interpreter::new())
+    }
+
+    /// Seid for the entry point of a project, whose imports look in `search_path`.
+    fn project(entry: PathBuf, search_path: interpreter::SearchPath) -> Self {
+        let interpreter = interpreter::Interpreter::with_search_path(search_path);
+        Seid::with_interpreter(entry, false, interpreter)
+    }
+
+    fn with_interpreter(
+        file_name: PathBuf,
+        use_prompt: bool,
+        interpreter: interpreter::Interpreter,
+    ) -> Self {
         Seid {
-            file_name: file_name,
+            file_name,
             repl: Repl::new(),
             had_error: false,
-            use_prompt: run_prompt,
+            use_prompt,
+            docs: Vec::new(),
+            ast: parser::Ast::new(),
+            interpreter,
         }
     }
 
@@ -98,24 +243,50 @@ impl Seid {
             Error::Repl(_) => {
                 eprintln!("{e:?}")
             }
-            Error::SyntaxError(_, _, _) => {
+            Error::SyntaxError(_, _, _) | Error::UnexpectedEof(_, _) | Error::ImportError(_, _) => {
                 eprintln!("{e:?}");
                 self.had_error = true;
             }
+            Error::RuntimeError(..) | Error::Thrown(_, _) => {
+                eprintln!("{e:?}");
+                self.print_stack_trace();
+                self.had_error = true;
+            }
             Error::Anyhow(_) => {
                 eprintln!("{e:?}")
             }
-            Error::ParsingError(_) => {
+            Error::ParsingError(_) | Error::FormatError(_) => {
                 eprintln!("{e:?}")
             }
         }
     }
 
+    fn print_stack_trace(&mut self) {
+        let file = if self.use_prompt {
+            "<repl>".to_string()
+        } else {
+            self.file_name.display().to_string()
+        };
+        let stack = self.interpreter.take_stack_trace();
+        eprintln!("Stack trace (most recent call first):");
+        for line in interpreter::stack_trace(&stack, &file) {
+            eprintln!("{}", line);
+        }
+    }
+
     fn run_prompt(&mut self) -> Result<(), Error> {
+        // Lines of a construct that is still incomplete, e.g. an unclosed parenthesis
+        let mut pending = String::new();
         loop {
-            match self.repl.readline.readline("(seid) > ") {
+            let prompt = if pending.is_empty() {
+                "(seid) > "
+            } else {
+                "(seid) . "
+            };
+            match self.repl.readline.readline(prompt) {
                 Err(ReadlineError::Interrupted) => {
                     // User pressed ctrl+c. Ignore it
+                    pending.clear();
                     println!("Type \"exit()\" to exit");
                 }
                 Err(ReadlineError::Eof) => {
@@ -124,33 +295,98 @@ impl Seid {
                 }
                 Err(err) => return Err(Error::Repl(err.to_string())),
                 Ok(line) => {
-                    if line.eq("exit()") {
+                    if pending.is_empty() && line.trim().eq("exit()") {
                         return Ok(());
                     }
-                    match self.run(line) {
+                    if pending.is_empty() && line.trim().eq(":help") {
+                        self.print_help();
+                        continue;
+                    }
+                    pending.push_str(&line);
+                    pending.push('\n');
+                    match self.run(&pending) {
                         Ok(()) => (),
+                        // Keep reading until the construct is complete, or an empty line gives up on it
+                        Err(Error::UnexpectedEof(_, _)) if !line.trim().is_empty() => continue,
                         Err(e) => self.handle_error(e),
                     };
+                    pending.clear();
                 }
             }
         }
     }
 
-    fn run_file(&self) -> Result<(), Error> {
+    fn print_help(&self) {
+        println!("Type Seid code to evaluate it, \":help\" for this message or \"exit()\" to exit");
+        if !self.docs.is_empty() {
+            println!();
+            for doc in &self.docs {
+                println!("{}", doc);
+            }
+        }
+    }
+
+    /// Parses the file and the modules it imports without running anything. Returns whether
+    /// there were no errors.
+    fn check(&mut self) -> bool {
+        match self.read_file().and_then(|contents| self.parse(&contents)) {
+            Ok(_) => true,
+            Err(e) => {
+                self.handle_error(e);
+                false
+            }
+        }
+    }
+
+    fn run_file(&mut self) -> Result<(), Error> {
+        let contents = self.read_file()?;
+        self.run(&contents)?;
+        Ok(())
+    }
+
+    fn read_file(&self) -> Result<String, Error> {
         let contents = std::fs::read_to_string(&self.file_name).with_context(|| {
             format!("could not read file `{}`", self.file_name.to_str().unwrap())
         })?;
-        self.run(contents)?;
+        Ok(contents)
+    }
+
+    fn run(&mut self, source: &str) -> Result<(), Error> {
+        for stmt in self.parse(source)? {
+            match self.ast[stmt].kind {
+                // The REPL shows the value of a bare expression, unless there is none
+                parser::StmtKind::Expression(expr) if self.use_prompt => {
+                    let value = self.interpreter.evaluate(&self.ast, expr)?;
+                    if value != interpreter::Value::Nil {
+                        println!("{}", value);
+                    }
+                }
+                _ => self.interpreter.execute(&self.ast, stmt)?,
+            }
+        }
         Ok(())
     }
 
-    fn run(&self, source: String) -> Result<(), Error> {
-        let mut scanner: Scanner = Scanner::new(source);
-        let tokens: &Vec<Token> = scanner.scan_tokens()?;
+    /// Parses `source` and loads the modules it imports, printing any warnings.
+    fn parse(&mut self, source: &str) -> Result<Vec<parser::StmtId>, Error> {
+        let mut parser = parser::Parser::new(Scanner::new(source), &mut self.ast)?;
+        let statements = parser.parse();
+        // An incomplete REPL entry is parsed again once more input arrives
+        if !matches!(statements, Err(Error::UnexpectedEof(_, _))) {
+            self.docs.extend_from_slice(parser.docs());
+        }
+        if statements.is_ok() {
+            for warning in parser.warnings() {
+                eprintln!("{}", warning);
+            }
+        }
 
-        for token in tokens {
-            println!("{}", token.to_string());
+        let statements = statements?;
+        // Imports are relative to the file, or in the REPL to the current directory
+        let file = (!self.use_prompt).then_some(self.file_name.as_path());
+        for warning in self.interpreter.load(&mut self.ast, &statements, file)? {
+            eprintln!("{}", warning);
         }
-        Ok(())
+        Ok(statements)
     }
 }
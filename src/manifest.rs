@@ -0,0 +1,445 @@
+use crate::interpreter::{Package, SearchPath};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+pub const MANIFEST: &str = "seid.toml";
+pub const LOCKFILE: &str = "seid.lock";
+
+const LOCKFILE_VERSION: u32 = 1;
+const LOCKFILE_HEADER: &str = "# Written by seid from seid.toml. Don't edit it by hand.\n";
+
+/// The contents of a `seid.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: PackageInfo,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageInfo {
+    pub name: String,
+    #[serde(default = "default_version")]
+    pub version: String,
+    /// The file that `seid run` runs, or that importing the package by name imports.
+    #[serde(default = "default_entry")]
+    pub entry: PathBuf,
+    /// The directories that imports search.
+    #[serde(default = "default_roots")]
+    pub roots: Vec<PathBuf>,
+}
+
+/// A dependency on another package on disk, by the directory of its manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dependency {
+    pub path: PathBuf,
+}
+
+fn default_version() -> String {
+    String::from("0.1.0")
+}
+
+fn default_entry() -> PathBuf {
+    PathBuf::from("main.sd")
+}
+
+fn default_roots() -> Vec<PathBuf> {
+    vec![PathBuf::from(".")]
+}
+
+/// A package of a project. Its paths are relative to its directory, which is relative to
+/// the directory of the project.
+#[derive(Debug)]
+pub struct Member {
+    pub manifest: Manifest,
+    pub directory: PathBuf,
+}
+
+/// A package together with every package it depends on, directly or not. Packages are
+/// told apart by name, so two packages in a project can't have the same name.
+#[derive(Debug)]
+pub struct Project {
+    /// The directory of the package's manifest.
+    pub root: PathBuf,
+    /// The package itself first, then its dependencies in the order they were found.
+    pub members: Vec<Member>,
+}
+
+/// What `seid.lock` records about a project.
+#[derive(Debug, Serialize)]
+struct Lockfile {
+    version: u32,
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Serialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    path: String,
+    dependencies: Vec<String>,
+}
+
+impl Project {
+    /// The project of the manifest at `path`, or else of the nearest `seid.toml` in the
+    /// current directory or one of its parents.
+    pub fn find(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let current = std::env::current_dir()?;
+                // Relative to the current directory, which keeps file names in messages short
+                let depth = current
+                    .ancestors()
+                    .position(|directory| directory.join(MANIFEST).is_file())
+                    .with_context(|| {
+                        format!(
+                            "could not find `{}` in `{}` or any parent directory",
+                            MANIFEST,
+                            current.display()
+                        )
+                    })?;
+                let root: PathBuf = std::iter::repeat_n("..", depth).collect();
+                root.join(MANIFEST)
+            }
+        };
+        let root = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        let manifest = read_manifest(&path)?;
+        let mut project = Project {
+            root,
+            members: vec![Member {
+                manifest,
+                directory: PathBuf::new(),
+            }],
+        };
+        let name = project.members[0].manifest.package.name.clone();
+        project.resolve(0, &mut vec![name])?;
+        Ok(project)
+    }
+
+    /// Adds the dependencies of the member at `index` that aren't in the project yet, and
+    /// theirs in turn. `chain` is the names of the members that led to it, for reporting a
+    /// cycle.
+    fn resolve(&mut self, index: usize, chain: &mut Vec<String>) -> Result<()> {
+        let member = &self.members[index];
+        let dependencies: Vec<(String, PathBuf)> = member
+            .manifest
+            .dependencies
+            .iter()
+            .map(|(name, dependency)| {
+                (
+                    name.clone(),
+                    normalize(&member.directory.join(&dependency.path)),
+                )
+            })
+            .collect();
+        for (name, directory) in dependencies {
+            if chain.contains(&name) {
+                chain.push(name);
+                bail!("dependency cycle: {}", chain.join(" -> "));
+            }
+            if let Some(other) = self
+                .members
+                .iter()
+                .find(|other| other.manifest.package.name == name)
+            {
+                if other.directory != directory {
+                    bail!(
+                        "two packages are named `{}`: `{}` and `{}`",
+                        name,
+                        display(&other.directory),
+                        display(&directory)
+                    );
+                }
+                continue;
+            }
+            let manifest = read_manifest(&self.root.join(&directory).join(MANIFEST))
+                .with_context(|| format!("could not load dependency `{}`", name))?;
+            if manifest.package.name != name {
+                bail!(
+                    "dependency `{}` at `{}` is a package named `{}`",
+                    name,
+                    display(&directory),
+                    manifest.package.name
+                );
+            }
+            self.members.push(Member {
+                manifest,
+                directory,
+            });
+            chain.push(name);
+            self.resolve(self.members.len() - 1, chain)?;
+            chain.pop();
+        }
+        Ok(())
+    }
+
+    /// The file that `seid run` runs.
+    pub fn entry(&self) -> PathBuf {
+        self.root.join(&self.members[0].manifest.package.entry)
+    }
+
+    /// Where the imports of the project look for modules: the roots of the package, its
+    /// dependencies by name, and then the directories in `SEID_PATH`.
+    pub fn search_path(&self) -> SearchPath {
+        let mut search_path = SearchPath::from_env();
+        let roots = self.roots(&self.members[0]);
+        search_path.roots.splice(0..0, roots);
+        for member in &self.members[1..] {
+            let directory = self.root.join(&member.directory);
+            let package = Package {
+                entry: directory.join(&member.manifest.package.entry),
+                roots: self.roots(member),
+            };
+            search_path
+                .packages
+                .insert(member.manifest.package.name.clone(), package);
+        }
+        search_path
+    }
+
+    fn roots(&self, member: &Member) -> Vec<PathBuf> {
+        let directory = self.root.join(&member.directory);
+        member
+            .manifest
+            .package
+            .roots
+            .iter()
+            .map(|root| normalize(&directory.join(root)))
+            .collect()
+    }
+
+    /// What `seid.lock` should say: the name, version and directory of every package and
+    /// the names of the packages it depends on.
+    pub fn lockfile(&self) -> String {
+        let mut package: Vec<LockedPackage> = self
+            .members
+            .iter()
+            .map(|member| LockedPackage {
+                name: member.manifest.package.name.clone(),
+                version: member.manifest.package.version.clone(),
+                path: display(&member.directory),
+                dependencies: member.manifest.dependencies.keys().cloned().collect(),
+            })
+            .collect();
+        package.sort_by(|a, b| a.name.cmp(&b.name));
+        let lockfile = Lockfile {
+            version: LOCKFILE_VERSION,
+            package,
+        };
+        // Serializing plain strings and lists can't fail
+        let body = toml::to_string(&lockfile).unwrap_or_default();
+        format!("{}{}", LOCKFILE_HEADER, body)
+    }
+
+    /// Writes `seid.lock` if it doesn't match the project, or with `locked` fails instead.
+    pub fn update_lockfile(&self, locked: bool) -> Result<()> {
+        let path = self.root.join(LOCKFILE);
+        let lockfile = self.lockfile();
+        if std::fs::read_to_string(&path).ok().as_deref() == Some(lockfile.as_str()) {
+            return Ok(());
+        }
+        if locked {
+            bail!(
+                "`{}` needs to be updated but --locked was passed",
+                path.display()
+            );
+        }
+        std::fs::write(&path, lockfile)
+            .with_context(|| format!("could not write file `{}`", path.display()))
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read file `{}`", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("could not parse `{}`", path.display()))
+}
+
+/// `path` without `.` and `directory/..`, worked out without looking at the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(normal.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                normal.pop();
+            }
+            _ => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// A directory relative to the project as it is shown to the user and in `seid.lock`.
+fn display(directory: &Path) -> String {
+    if directory.as_os_str().is_empty() {
+        return String::from(".");
+    }
+    directory.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Files;
+
+    /// The project with its manifest at the root of `files`.
+    fn find(files: &Files) -> Result<Project> {
+        Project::find(Some(&files.path(MANIFEST)))
+    }
+
+    fn error(files: &[(&str, &str)]) -> String {
+        find(&Files::new(files)).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn dependencies_are_found_once_in_the_order_they_are_met() {
+        let files = Files::new(&[
+            (
+                "seid.toml",
+                "[package]\nname = \"app\"\nroots = [\"src\"]\n\n[dependencies]\nb = { path = \"libs/b\" }\na = { path = \"libs/a\" }\n",
+            ),
+            (
+                "libs/a/seid.toml",
+                "[package]\nname = \"a\"\n\n[dependencies]\nb = { path = \"../b\" }\n",
+            ),
+            (
+                "libs/b/seid.toml",
+                "[package]\nname = \"b\"\nentry = \"lib.sd\"\n",
+            ),
+        ]);
+        let project = find(&files).unwrap();
+        let members: Vec<_> = project
+            .members
+            .iter()
+            .map(|member| {
+                format!(
+                    "{} at {}",
+                    member.manifest.package.name,
+                    display(&member.directory)
+                )
+            })
+            .collect();
+        // `a` comes first because dependencies are listed by name
+        assert_eq!(members, ["app at .", "a at libs/a", "b at libs/b"]);
+        assert_eq!(project.entry(), files.path("main.sd"));
+
+        let search_path = project.search_path();
+        assert_eq!(search_path.roots[0], files.path("src"));
+        let b = &search_path.packages["b"];
+        assert_eq!(b.entry, files.path("libs/b/lib.sd"));
+        assert_eq!(b.roots, [files.path("libs/b")]);
+    }
+
+    #[test]
+    fn a_dependency_cycle_is_an_error() {
+        let message = error(&[
+            (
+                "seid.toml",
+                "[package]\nname = \"app\"\n\n[dependencies]\na = { path = \"a\" }\n",
+            ),
+            (
+                "a/seid.toml",
+                "[package]\nname = \"a\"\n\n[dependencies]\nb = { path = \"../b\" }\n",
+            ),
+            (
+                "b/seid.toml",
+                "[package]\nname = \"b\"\n\n[dependencies]\na = { path = \"../a\" }\n",
+            ),
+        ]);
+        assert_eq!(message, "dependency cycle: app -> a -> b -> a");
+    }
+
+    #[test]
+    fn two_packages_with_the_same_name_are_an_error() {
+        let message = error(&[
+            (
+                "seid.toml",
+                "[package]\nname = \"app\"\n\n[dependencies]\na = { path = \"a\" }\nb = { path = \"b\" }\n",
+            ),
+            ("a/seid.toml", "[package]\nname = \"a\"\n"),
+            (
+                "b/seid.toml",
+                "[package]\nname = \"b\"\n\n[dependencies]\na = { path = \"../other\" }\n",
+            ),
+            ("other/seid.toml", "[package]\nname = \"a\"\n"),
+        ]);
+        assert_eq!(message, "two packages are named `a`: `a` and `other`");
+    }
+
+    #[test]
+    fn a_dependency_must_have_the_name_it_is_listed_under() {
+        let message = error(&[
+            (
+                "seid.toml",
+                "[package]\nname = \"app\"\n\n[dependencies]\nutil = { path = \"lib\" }\n",
+            ),
+            ("lib/seid.toml", "[package]\nname = \"helpers\"\n"),
+        ]);
+        assert_eq!(
+            message,
+            "dependency `util` at `lib` is a package named `helpers`"
+        );
+    }
+
+    #[test]
+    fn the_lockfile_lists_every_package_and_locked_refuses_to_change_it() {
+        let files = Files::new(&[
+            (
+                "seid.toml",
+                "[package]\nname = \"app\"\nversion = \"1.2.0\"\n\n[dependencies]\nutil = { path = \"lib\" }\n",
+            ),
+            ("lib/seid.toml", "[package]\nname = \"util\"\n"),
+        ]);
+        let project = find(&files).unwrap();
+        assert_eq!(
+            project.lockfile(),
+            "# Written by seid from seid.toml. Don't edit it by hand.
+version = 1
+
+[[package]]
+name = \"app\"
+version = \"1.2.0\"
+path = \".\"
+dependencies = [\"util\"]
+
+[[package]]
+name = \"util\"
+version = \"0.1.0\"
+path = \"lib\"
+dependencies = []
+"
+        );
+        assert!(project.update_lockfile(true).is_err());
+        assert!(!files.path(LOCKFILE).exists());
+        project.update_lockfile(false).unwrap();
+        let written = std::fs::read_to_string(files.path(LOCKFILE)).unwrap();
+        assert_eq!(written, project.lockfile());
+        project.update_lockfile(true).unwrap();
+
+        // A changed manifest needs a new lockfile
+        let manifest = files.path("lib").join(MANIFEST);
+        std::fs::write(
+            manifest,
+            "[package]\nname = \"util\"\nversion = \"0.2.0\"\n",
+        )
+        .unwrap();
+        let project = find(&files).unwrap();
+        let message = project.update_lockfile(true).unwrap_err().to_string();
+        assert_eq!(
+            message,
+            format!(
+                "`{}` needs to be updated but --locked was passed",
+                files.path(LOCKFILE).display()
+            )
+        );
+    }
+}
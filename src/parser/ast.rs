@@ -0,0 +1,271 @@
+use crate::parser::expr::{Expr, ExprDisplay};
+use crate::parser::stmt::{Stmt, StmtDisplay};
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::ops::Index;
+use std::rc::Rc;
+
+/// A typed index into one of the `Ast` arenas.
+pub trait NodeId: Copy {
+    fn from_index(index: usize) -> Self;
+    fn index(self) -> usize;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+impl NodeId for ExprId {
+    fn from_index(index: usize) -> Self {
+        ExprId(index as u32)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StmtId(u32);
+
+impl NodeId for StmtId {
+    fn from_index(index: usize) -> Self {
+        StmtId(index as u32)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Owns every node of the parsed program in contiguous vectors. Nodes refer to each other
+/// by id, and ids stay valid for as long as the `Ast` lives, so the REPL keeps one `Ast`
+/// for the whole session and ids are unique across lines.
+///
+/// String literals are interned here rather than with the identifiers in `Symbol`, so equal
+/// literals share one allocation for the session but are freed along with the `Ast`.
+#[derive(Debug, Default)]
+pub struct Ast {
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+    strings: HashSet<Rc<str>>,
+}
+
+impl Ast {
+    pub fn new() -> Self {
+        Ast::default()
+    }
+
+    pub fn alloc_expr(&mut self, expr: Expr) -> ExprId {
+        self.exprs.push(expr);
+        ExprId::from_index(self.exprs.len() - 1)
+    }
+
+    pub fn expr(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.index()]
+    }
+
+    pub fn expr_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id.index()]
+    }
+
+    pub fn display(&self, id: ExprId) -> ExprDisplay<'_> {
+        ExprDisplay { ast: self, id }
+    }
+
+    pub fn alloc_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId::from_index(self.stmts.len() - 1)
+    }
+
+    pub fn stmt(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id.index()]
+    }
+
+    pub fn stmt_mut(&mut self, id: StmtId) -> &mut Stmt {
+        &mut self.stmts[id.index()]
+    }
+
+    pub fn display_stmt(&self, id: StmtId) -> StmtDisplay<'_> {
+        StmtDisplay { ast: self, id }
+    }
+
+    /// The string literal `s`, shared with every equal literal parsed into this `Ast`.
+    pub fn intern_str(&mut self, s: &str) -> Rc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return Rc::clone(interned);
+        }
+        let interned: Rc<str> = Rc::from(s);
+        self.strings.insert(Rc::clone(&interned));
+        interned
+    }
+}
+
+impl Index<ExprId> for Ast {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        self.expr(id)
+    }
+}
+
+impl Index<StmtId> for Ast {
+    type Output = Stmt;
+
+    fn index(&self, id: StmtId) -> &Stmt {
+        self.stmt(id)
+    }
+}
+
+/// Per-node results of a later pass (e.g. resolved scope depths or inferred types),
+/// stored next to the AST instead of inside it.
+#[derive(Debug, Clone)]
+pub struct SideTable<I: NodeId, T> {
+    values: Vec<Option<T>>,
+    _id: PhantomData<I>,
+}
+
+impl<I: NodeId, T> SideTable<I, T> {
+    pub fn new() -> Self {
+        SideTable {
+            values: Vec::new(),
+            _id: PhantomData,
+        }
+    }
+
+    pub fn insert(&mut self, id: I, value: T) {
+        let index = id.index();
+        if index >= self.values.len() {
+            self.values.resize_with(index + 1, || None);
+        }
+        self.values[index] = Some(value);
+    }
+
+    pub fn get(&self, id: I) -> Option<&T> {
+        self.values.get(id.index()).and_then(|v| v.as_ref())
+    }
+}
+
+impl<I: NodeId, T> Default for SideTable<I, T> {
+    fn default() -> Self {
+        SideTable::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::bench::expression_source;
+    use crate::parser::{ExprKind, LiteralOp, Parser, StmtKind};
+    use crate::{Scanner, Span};
+    use std::time::Instant;
+
+    fn literal(ast: &mut Ast, n: f64) -> ExprId {
+        ast.alloc_expr(Expr {
+            span: Span::default(),
+            kind: ExprKind::Literal(LiteralOp::Number(n)),
+        })
+    }
+
+    #[test]
+    fn ids_index_the_arena_in_allocation_order() {
+        let mut ast = Ast::new();
+        let first = literal(&mut ast, 1.0);
+        let second = literal(&mut ast, 2.0);
+        assert_eq!((first.index(), second.index()), (0, 1));
+        assert!(matches!(
+            ast[second].kind,
+            ExprKind::Literal(LiteralOp::Number(n)) if n == 2.0
+        ));
+    }
+
+    #[test]
+    fn ids_stay_unique_across_parses_into_one_ast() {
+        let mut ast = Ast::new();
+        let first = Parser::new(Scanner::new("1 + 2;"), &mut ast)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let second = Parser::new(Scanner::new("3 + 4;"), &mut ast)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_ne!(first, second);
+        assert_eq!(ast.display_stmt(first[0]).to_string(), "1 + 2;");
+        assert_eq!(ast.display_stmt(second[0]).to_string(), "3 + 4;");
+    }
+
+    #[test]
+    fn equal_string_literals_share_one_allocation() {
+        let mut ast = Ast::new();
+        let statements = Parser::new(Scanner::new("\"a\"; ({a: 1}); \"b\";"), &mut ast)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let mut strings = Vec::new();
+        for id in statements {
+            let StmtKind::Expression(expr) = ast[id].kind else {
+                panic!("not an expression");
+            };
+            let expr = match ast[expr].kind {
+                ExprKind::Grouping(inner) => inner,
+                _ => expr,
+            };
+            match &ast[expr].kind {
+                ExprKind::Literal(LiteralOp::Str(s)) => strings.push(Rc::clone(s)),
+                ExprKind::Map(entries) => match &ast[entries[0].0].kind {
+                    ExprKind::Literal(LiteralOp::Str(s)) => strings.push(Rc::clone(s)),
+                    other => panic!("unexpected key {:?}", other),
+                },
+                other => panic!("unexpected expression {:?}", other),
+            }
+        }
+        // A bare map key is a string literal too
+        assert!(Rc::ptr_eq(&strings[0], &strings[1]));
+        assert!(!Rc::ptr_eq(&strings[0], &strings[2]));
+        let again = ast.intern_str("b");
+        assert!(Rc::ptr_eq(&again, &strings[2]));
+    }
+
+    #[test]
+    fn side_tables_are_sparse() {
+        let mut ast = Ast::new();
+        let ids: Vec<ExprId> = (0..4).map(|n| literal(&mut ast, n as f64)).collect();
+        let mut table = SideTable::new();
+        table.insert(ids[2], "two");
+        assert_eq!(table.get(ids[2]), Some(&"two"));
+        assert_eq!(table.get(ids[0]), None);
+        assert_eq!(table.get(ids[3]), None);
+        table.insert(ids[2], "again");
+        assert_eq!(table.get(ids[2]), Some(&"again"));
+    }
+
+    /// Parses into one arena, as the REPL does, and reports its size. Run it with
+    /// `cargo test --release -- --ignored --nocapture bench`.
+    ///
+    /// Against the boxed `Expr` tree that the arena replaced, in a release build on the
+    /// expression of `bench_parse_expression` with only the operators both knew, a parse
+    /// took 12-15 ms instead of 19-22 ms. The arena, spare capacity included, held 5.0 MB
+    /// where the boxes took 4.8 MB.
+    #[test]
+    #[ignore]
+    fn bench_arena() {
+        const TREES: usize = 5;
+        let source = expression_source(20_000);
+        let mut ast = Ast::new();
+        let start = Instant::now();
+        for _ in 0..TREES {
+            Parser::new(Scanner::new(&source), &mut ast)
+                .unwrap()
+                .parse()
+                .unwrap();
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "{} trees in {:?}: {} expressions, {} KB of arena",
+            TREES,
+            elapsed,
+            ast.exprs.len(),
+            ast.exprs.capacity() * std::mem::size_of::<Expr>() / 1024
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Benchmarks of the parser, ignored by a plain `cargo test`. Run them with
+//! `cargo test --release -- --ignored --nocapture bench`.
+
+use crate::parser::{Ast, Parser};
+use crate::Scanner;
+use std::time::Instant;
+
+// Terms of the generated expression, about 445 KB of source
+const TERMS: usize = 20_000;
+const RUNS: usize = 20;
+
+/// A long expression that mixes every precedence level of the binary operators, with
+/// groupings and unary operators that stay a few levels deep however long it gets.
+pub(super) fn expression_source(terms: usize) -> String {
+    let operators = ["+", "*", "-", "/", "<", "==", "%", "&", "|", "<<", ">="];
+    let mut source = String::new();
+    for i in 0..terms {
+        if i > 0 {
+            source.push_str(&format!(" {} ", operators[i % operators.len()]));
+        }
+        match i % 4 {
+            0 => source.push_str(&format!("(value_{} + {})", i % 97, i)),
+            1 => source.push_str(&format!("-{}.5", i)),
+            2 => source.push_str(&format!("!(x == {} * y)", i % 13)),
+            _ => source.push_str(&format!("name_{}", i % 31)),
+        }
+    }
+    source.push(';');
+    source
+}
+
+/// Against the per-precedence parser that the Pratt parser replaced, in a release build on
+/// this expression with numbers for names (that parser had no variables) and only the
+/// operators both knew, each took 22-25 ms a parse and built the same 9.6 MB tree.
+#[test]
+#[ignore]
+fn bench_parse_expression() {
+    let source = expression_source(TERMS);
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let mut ast = Ast::new();
+        Parser::new(Scanner::new(&source), &mut ast)
+            .unwrap()
+            .parse()
+            .unwrap();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "parsed {} KB {} times in {:?}, {:?} per parse",
+        source.len() / 1024,
+        RUNS,
+        elapsed,
+        elapsed / RUNS as u32
+    );
+}
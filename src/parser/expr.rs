@@ -1,63 +1,303 @@
-use crate::Token;
+use crate::parser::ast::{Ast, ExprId};
+use crate::parser::pattern::Pattern;
+use crate::parser::printer::Printer;
+use crate::{Span, Symbol, TokenType};
 use std::fmt;
+use std::rc::Rc;
 /*
 Expression grammar:
 
-expression     → equality ;
+expression     → comma ;
+comma          → assignment ( "," assignment )* ;
+assignment     → target ( "=" | "+=" | "-=" | "*=" | "/=" | "%=" ) assignment
+               | destructure "=" assignment | conditional ;
+target         → IDENTIFIER | call "[" expression "]" ;
+destructure    → "(" elements? ")" | "[" elements? "]" | "{" entries? "}" ;
+conditional    → logic_or ( "?" expression ":" conditional )? ;
+logic_or       → logic_and ( "or" logic_and )* ;
+logic_and      → equality ( "and" equality )* ;
 equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )*;
+comparison     → bit_or ( ( ">" | ">=" | "<" | "<=" ) bit_or )*;
+bit_or         → bit_xor ( "|" bit_xor )* ;
+bit_xor        → bit_and ( "^" bit_and )* ;
+bit_and        → shift ( "&" shift )* ;
+shift          → term ( ( "<<" | ">>" ) term )* ;
 term           → factor ( ( "-" | "+" )  factor )* ;
-factor         → unary ( ( "/" | "*" ) unary )* ;
-unary          → ( "-" | "!" ) unary | primary ;
-primary        → NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" ;
+factor         → unary ( ( "/" | "*" | "%" | "~/" ) unary )* ;
+unary          → ( "-" | "!" | "~" ) unary | ( "++" | "--" ) target | exponent ;
+exponent       → postfix ( "**" unary )? ;
+postfix        → call | target ( "++" | "--" ) ;
+call           → primary ( "(" elements? ")" | "[" index "]" | "." IDENTIFIER )* ;
+index          → expression | expression? ":" expression? ;
+elements       → element ( "," element )* ","? ;
+element        → "..."? assignment ;
+primary        → NUMBER | STRING | IDENTIFIER | "true" | "false" | "nil" | "(" assignment ")"
+               | "(" ( element "," elements? )? ")" | "[" elements? "]" | "{" entries? "}"
+               | lambda | match ;
+entries        → entry ( "," entry )* ","? ;
+entry          → ( IDENTIFIER | element ) ":" element | IDENTIFIER ;
+lambda         → "lambda" ( pattern ( "," pattern )* )? ":" element ;
+match          → "match" "(" expression ")" "{" ( arm ( "," arm )* ","? )? "}" ;
+arm            → pattern ( "if" expression )? "=>" element ;
+
+A "{" that starts a statement opens a block, anywhere else it opens a map. A bare
+identifier as a map key is a string, `{a: 1}` is `{"a": 1}`, and `{(a): 1}` uses the value
+of the variable. A bare identifier on its own, `{a}`, is `{"a": a}`.
+
+Parentheses around an element followed by a comma make a tuple, so `(a)` is a grouping
+and `(a,)` a tuple with one element. Inside parentheses the comma doesn't mean the comma
+operator. "..." spreads a list or tuple into the surrounding elements, or in a
+destructuring assignment collects the remaining values. The elements of a destructuring
+assignment are targets or destructure further, and "..." only collects into a target.
+
+A match tries its arms from top to bottom. The first arm whose pattern matches the value,
+and whose guard is truthy if it has one, gives the value of the match.
 */
 
-pub enum Expr<'a> {
+/// An expression node. Sub-expressions are ids into the `Ast` that owns this node.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub span: Span,
+    pub kind: ExprKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
     Literal(LiteralOp),
-    Unary(&'a Token, Box<Expr<'a>>),
-    Binary(Box<Expr<'a>>, &'a Token, Box<Expr<'a>>),
-    Grouping(Box<Expr<'a>>),
+    Variable(Symbol),
+    Unary(UnaryOp, ExprId),
+    Binary(ExprId, BinaryOp, ExprId),
+    /// `and` and `or`, which short-circuit.
+    Logical(ExprId, LogicalOp, ExprId),
+    /// Target, operator of a compound assignment like `+=`, and value. The target is
+    /// one of the expressions that name a place, e.g. a variable.
+    Assign(ExprId, Option<BinaryOp>, ExprId),
+    /// `++x`, `x++`, `--x` and `x--`: the target, `Add` or `Subtract`, and whether the
+    /// operator comes first, which gives the updated value instead of the old one.
+    Increment(ExprId, BinaryOp, bool),
+    /// Callee and arguments.
+    Call(ExprId, Vec<ExprId>),
+    /// `object[index]`.
+    Index(ExprId, ExprId),
+    /// `object[start:end]`, either bound may be left out.
+    Slice(ExprId, Option<ExprId>, Option<ExprId>),
+    /// `object.name`.
+    Get(ExprId, Symbol),
+    List(Vec<ExprId>),
+    Tuple(Vec<ExprId>),
+    /// `...xs` as an element of a list, tuple or argument list.
+    Spread(ExprId),
+    /// Keys and values of `{key: value}`, in order.
+    Map(Vec<(ExprId, ExprId)>),
+    /// Parameters and body of `lambda a, b: body`.
+    Lambda(Vec<Pattern>, ExprId),
+    /// Condition, then branch and else branch of `a ? b : c`.
+    Conditional(ExprId, ExprId, ExprId),
+    Grouping(ExprId),
+    /// The matched value and the arms of `match (value) { ... }`.
+    Match(ExprId, Vec<Arm>),
+}
+
+/// `pattern if guard => body`, an arm of a match.
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub guard: Option<ExprId>,
+    pub body: ExprId,
+}
+
+/// Displays an expression of an `Ast`, see `Ast::display`.
+pub struct ExprDisplay<'a> {
+    pub ast: &'a Ast,
+    pub id: ExprId,
+}
+
+impl fmt::Display for ExprDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&Printer::expr(self.ast, self.id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    BitNot,
 }
 
-impl fmt::Display for Expr<'_> {
+impl fmt::Display for UnaryOp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Expr::Literal(op) => write!(f, "{}", &op),
-            Expr::Unary(t, exp) => {
-                if let Ok(s) = std::str::from_utf8(&t.lexeme) {
-                    return write!(f, "( {} {})", s, &*exp);
-                } else {
-                    return Err(fmt::Error);
-                }
-            }
-            Expr::Binary(exp_lhs, t, exp_rhs) => {
-                if let Ok(s) = std::str::from_utf8(&t.lexeme) {
-                    return write!(f, "( {} {} {})", s, &*exp_lhs, &*exp_rhs);
-                } else {
-                    return Err(fmt::Error);
-                }
-            }
-            Expr::Grouping(exp) => write!(f, "( group {})", &*exp),
+            UnaryOp::Negate => write!(f, "-"),
+            UnaryOp::Not => write!(f, "!"),
+            UnaryOp::BitNot => write!(f, "~"),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// Remainder with the sign of the dividend.
+    Modulo,
+    /// Division truncated towards zero, spelled `~/`.
+    IntDivide,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    /// Evaluates both operands and gives the right one.
+    Comma,
+}
+
+impl BinaryOp {
+    pub fn from_token(token_type: TokenType) -> Option<Self> {
+        let op = match token_type {
+            TokenType::Plus => BinaryOp::Add,
+            TokenType::Minus => BinaryOp::Subtract,
+            TokenType::Star => BinaryOp::Multiply,
+            TokenType::Slash => BinaryOp::Divide,
+            TokenType::Percent => BinaryOp::Modulo,
+            TokenType::TildeSlash => BinaryOp::IntDivide,
+            TokenType::StarStar => BinaryOp::Power,
+            TokenType::Ampersand => BinaryOp::BitAnd,
+            TokenType::Pipe => BinaryOp::BitOr,
+            TokenType::Caret => BinaryOp::BitXor,
+            TokenType::LessLess => BinaryOp::ShiftLeft,
+            TokenType::GreaterGreater => BinaryOp::ShiftRight,
+            TokenType::EqualEqual => BinaryOp::Equal,
+            TokenType::BangEqual => BinaryOp::NotEqual,
+            TokenType::Greater => BinaryOp::Greater,
+            TokenType::GreaterEqual => BinaryOp::GreaterEqual,
+            TokenType::Less => BinaryOp::Less,
+            TokenType::LessEqual => BinaryOp::LessEqual,
+            TokenType::Comma => BinaryOp::Comma,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    pub fn token_type(self) -> TokenType {
+        match self {
+            BinaryOp::Add => TokenType::Plus,
+            BinaryOp::Subtract => TokenType::Minus,
+            BinaryOp::Multiply => TokenType::Star,
+            BinaryOp::Divide => TokenType::Slash,
+            BinaryOp::Modulo => TokenType::Percent,
+            BinaryOp::IntDivide => TokenType::TildeSlash,
+            BinaryOp::Power => TokenType::StarStar,
+            BinaryOp::BitAnd => TokenType::Ampersand,
+            BinaryOp::BitOr => TokenType::Pipe,
+            BinaryOp::BitXor => TokenType::Caret,
+            BinaryOp::ShiftLeft => TokenType::LessLess,
+            BinaryOp::ShiftRight => TokenType::GreaterGreater,
+            BinaryOp::Equal => TokenType::EqualEqual,
+            BinaryOp::NotEqual => TokenType::BangEqual,
+            BinaryOp::Greater => TokenType::Greater,
+            BinaryOp::GreaterEqual => TokenType::GreaterEqual,
+            BinaryOp::Less => TokenType::Less,
+            BinaryOp::LessEqual => TokenType::LessEqual,
+            BinaryOp::Comma => TokenType::Comma,
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::IntDivide => "~/",
+            BinaryOp::Power => "**",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Comma => ",",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// `and` gives its left operand if that is falsy and `or` if it is truthy, otherwise both
+/// give their right operand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+impl LogicalOp {
+    pub fn token_type(self) -> TokenType {
+        match self {
+            LogicalOp::And => TokenType::And,
+            LogicalOp::Or => TokenType::Or,
+        }
+    }
+}
+
+impl fmt::Display for LogicalOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogicalOp::And => write!(f, "and"),
+            LogicalOp::Or => write!(f, "or"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum LiteralOp {
     Number(f64),
-    Str(String),
+    Str(Rc<str>),
     True,
     False,
     Nil,
 }
 
+/// Literals display as Seid source, so strings are quoted and escaped.
 impl fmt::Display for LiteralOp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             LiteralOp::Number(n) => write!(f, "{}", n),
-            LiteralOp::Str(s) => write!(f, "{}", s),
-            LiteralOp::True => write!(f, "True"),
-            LiteralOp::False => write!(f, "False"),
-            LiteralOp::Nil => write!(f, "Nil"),
+            LiteralOp::Str(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\0' => write!(f, "\\0")?,
+                        _ => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            LiteralOp::True => write!(f, "true"),
+            LiteralOp::False => write!(f, "false"),
+            LiteralOp::Nil => write!(f, "nil"),
         }
     }
 }
@@ -1,2 +1,18 @@
+mod ast;
+#[cfg(test)]
+mod bench;
 mod expr;
+#[allow(clippy::module_inception)]
 mod parser;
+mod pattern;
+mod printer;
+mod stmt;
+pub mod visit;
+
+pub use ast::{Ast, ExprId, NodeId, SideTable, StmtId};
+pub use expr::{Arm, BinaryOp, Expr, ExprDisplay, ExprKind, LiteralOp, LogicalOp, UnaryOp};
+pub use parser::Parser;
+pub use pattern::Pattern;
+pub use printer::Printer;
+pub use stmt::{Stmt, StmtDisplay, StmtKind};
+pub use visit::{MutVisitor, Visitor};
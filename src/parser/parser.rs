@@ -1,47 +1,829 @@
 use crate::Error;
 use crate::{
-    parser::expr::{Expr, LiteralOp},
-    Literal, Token, TokenType,
+    parser::ast::{Ast, ExprId, StmtId},
+    parser::expr::{Arm, BinaryOp, Expr, ExprKind, LiteralOp, LogicalOp, UnaryOp},
+    parser::pattern::Pattern,
+    parser::stmt::{Stmt, StmtKind},
+    Literal, Scanner, Span, Symbol, Token, TokenType,
 };
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::rc::Rc;
 
-struct Parser {
-    tokens: Vec<Token>,
-    current: AtomicUsize,
+type PrefixFn<'a> = fn(&mut Parser<'a>) -> Result<ExprId, Error>;
+type InfixFn<'a> = fn(&mut Parser<'a>, ExprId, u8) -> Result<ExprId, Error>;
+
+/// How a token parses at the start of an expression (prefix) and after one (infix).
+struct ParseRule<'a> {
+    prefix: Option<PrefixFn<'a>>,
+    infix: Option<InfixFn<'a>>,
+    /// Binding power on the left and right of an infix operator. Left-associative
+    /// operators bind tighter on their right, right-associative ones on their left.
+    power: (u8, u8),
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser {
-            tokens,
-            current: AtomicUsize::new(0),
+// Binding powers, from loosest to tightest
+const NONE: (u8, u8) = (0, 0);
+const COMMA: (u8, u8) = (1, 2);
+const ASSIGNMENT: (u8, u8) = (4, 3);
+const CONDITIONAL: (u8, u8) = (6, 5);
+const LOGIC_OR: (u8, u8) = (7, 8);
+const LOGIC_AND: (u8, u8) = (9, 10);
+const EQUALITY: (u8, u8) = (11, 12);
+const COMPARISON: (u8, u8) = (13, 14);
+const BIT_OR: (u8, u8) = (15, 16);
+const BIT_XOR: (u8, u8) = (17, 18);
+const BIT_AND: (u8, u8) = (19, 20);
+const SHIFT: (u8, u8) = (21, 22);
+const TERM: (u8, u8) = (23, 24);
+const FACTOR: (u8, u8) = (25, 26);
+const UNARY: u8 = 27;
+// Binds tighter than a unary operator on its left, so `-2 ** 2` is `-(2 ** 2)`
+const EXPONENT: (u8, u8) = (29, 28);
+// `x++` takes no right operand
+const POSTFIX: (u8, u8) = (31, 0);
+// Calls, indexing and property access
+const CALL: (u8, u8) = (33, 0);
+
+/// The operator table. Adding an operator means adding its token here.
+fn rule<'a>(token_type: TokenType) -> ParseRule<'a> {
+    let (prefix, infix, power): (Option<PrefixFn<'a>>, Option<InfixFn<'a>>, _) = match token_type {
+        TokenType::LeftParen => (Some(Parser::grouping), Some(Parser::call), CALL),
+        TokenType::LeftBracket => (Some(Parser::list), Some(Parser::index), CALL),
+        TokenType::LeftBrace => (Some(Parser::map), None, NONE),
+        TokenType::Dot => (None, Some(Parser::get), CALL),
+        TokenType::Lambda => (Some(Parser::lambda), None, NONE),
+        TokenType::Match => (Some(Parser::match_expression), None, NONE),
+        TokenType::Comma => (None, Some(Parser::binary), COMMA),
+        TokenType::Question => (None, Some(Parser::conditional), CONDITIONAL),
+        TokenType::Or => (None, Some(Parser::logical), LOGIC_OR),
+        TokenType::And => (None, Some(Parser::logical), LOGIC_AND),
+        TokenType::Equal
+        | TokenType::PlusEqual
+        | TokenType::MinusEqual
+        | TokenType::StarEqual
+        | TokenType::SlashEqual
+        | TokenType::PercentEqual => (None, Some(Parser::assignment), ASSIGNMENT),
+        TokenType::PlusPlus | TokenType::MinusMinus => (
+            Some(Parser::prefix_increment),
+            Some(Parser::postfix_increment),
+            POSTFIX,
+        ),
+        TokenType::Minus => (Some(Parser::unary), Some(Parser::binary), TERM),
+        TokenType::Plus => (None, Some(Parser::binary), TERM),
+        TokenType::Slash | TokenType::Star | TokenType::Percent | TokenType::TildeSlash => {
+            (None, Some(Parser::binary), FACTOR)
+        }
+        TokenType::StarStar => (None, Some(Parser::binary), EXPONENT),
+        TokenType::Pipe => (None, Some(Parser::binary), BIT_OR),
+        TokenType::Caret => (None, Some(Parser::binary), BIT_XOR),
+        TokenType::Ampersand => (None, Some(Parser::binary), BIT_AND),
+        TokenType::LessLess | TokenType::GreaterGreater => (None, Some(Parser::binary), SHIFT),
+        TokenType::Bang | TokenType::Tilde => (Some(Parser::unary), None, NONE),
+        TokenType::BangEqual | TokenType::EqualEqual => (None, Some(Parser::binary), EQUALITY),
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
+            (None, Some(Parser::binary), COMPARISON)
+        }
+        TokenType::Identifier => (Some(Parser::variable), None, NONE),
+        TokenType::Number
+        | TokenType::String
+        | TokenType::False
+        | TokenType::True
+        | TokenType::Nil => (Some(Parser::literal), None, NONE),
+        _ => (None, None, NONE),
+    };
+    ParseRule {
+        prefix,
+        infix,
+        power,
+    }
+}
+
+/// Binding power of an expression on its left and right, for printing the minimal
+/// parentheses. `None` for expressions that can't be split by a neighbouring operator.
+pub(crate) fn expr_power(kind: &ExprKind) -> Option<(u8, u8)> {
+    match kind {
+        ExprKind::Binary(_, op, _) => Some(rule(op.token_type()).power),
+        ExprKind::Logical(_, op, _) => Some(rule(op.token_type()).power),
+        ExprKind::Conditional(..) => Some(CONDITIONAL),
+        ExprKind::Assign(..) => Some(ASSIGNMENT),
+        // Nothing binds a prefix operator from the left, or a postfix one from the right
+        ExprKind::Unary(..) => Some((u8::MAX, UNARY)),
+        ExprKind::Increment(_, _, true) => Some((u8::MAX, POSTFIX.0)),
+        ExprKind::Increment(_, _, false) => Some((POSTFIX.0, u8::MAX)),
+        ExprKind::Call(..) | ExprKind::Index(..) | ExprKind::Slice(..) | ExprKind::Get(..) => {
+            Some((CALL.0, u8::MAX))
+        }
+        // The body runs up to the next comma. On the left it binds like an assignment, so
+        // `f = lambda x: x` needs no parentheses.
+        ExprKind::Lambda(..) => Some((ASSIGNMENT.0, COMMA.1)),
+        ExprKind::Literal(_)
+        | ExprKind::Variable(_)
+        | ExprKind::Grouping(_)
+        | ExprKind::List(_)
+        | ExprKind::Tuple(_)
+        | ExprKind::Spread(_)
+        | ExprKind::Map(_)
+        | ExprKind::Match(..) => None,
+    }
+}
+
+/// The value of a number, string, `true`, `false` or `nil` token. Strings are interned in
+/// `ast`.
+fn literal_op(token: &Token, ast: &mut Ast) -> Option<LiteralOp> {
+    let op = match (token.token_type, &token.literal) {
+        (TokenType::False, _) => LiteralOp::False,
+        (TokenType::True, _) => LiteralOp::True,
+        (TokenType::Nil, _) => LiteralOp::Nil,
+        (_, Some(Literal::Str(s))) => LiteralOp::Str(ast.intern_str(s)),
+        (_, Some(Literal::Number(f))) => LiteralOp::Number(*f),
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// `++` adds one, `--` subtracts one.
+fn increment_op(token_type: TokenType) -> BinaryOp {
+    if token_type == TokenType::PlusPlus {
+        BinaryOp::Add
+    } else {
+        BinaryOp::Subtract
+    }
+}
+
+/// Parses statements and expressions into an `Ast`, pulling tokens from the scanner only
+/// as they are needed.
+pub struct Parser<'a> {
+    scanner: Scanner<'a>,
+    ast: &'a mut Ast,
+    current: Token<'a>,
+    previous: Option<Token<'a>>,
+    docs: Vec<String>,
+    warnings: Vec<String>,
+    // How many function bodies enclose the current token, for rejecting a stray `return`
+    functions: usize,
+    // How many loops enclose the current token within the innermost function
+    loops: usize,
+    // Labels of the loops enclosing the current token within the innermost function
+    labels: Vec<Symbol>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(scanner: Scanner<'a>, ast: &'a mut Ast) -> Result<Self, Error> {
+        let mut parser = Parser {
+            scanner,
+            ast,
+            current: Token::new(TokenType::Eof, "", None, Span::default()),
+            previous: None,
+            docs: Vec::new(),
+            warnings: Vec::new(),
+            functions: 0,
+            loops: 0,
+            labels: Vec::new(),
+        };
+        parser.current = parser.pull()?;
+        Ok(parser)
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<StmtId>, Error> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            statements.push(self.module_declaration()?);
+        }
+        Ok(statements)
+    }
+
+    /// Doc comments seen so far. They are not part of the grammar.
+    pub fn docs(&self) -> &[String] {
+        &self.docs
+    }
+
+    /// Code that parses but can't do what it says, e.g. a match arm that no value reaches.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    fn pull(&mut self) -> Result<Token<'a>, Error> {
+        loop {
+            match self.scanner.next_token() {
+                Some(Ok(token)) if token.token_type == TokenType::DocComment => {
+                    if let Some(Literal::Str(doc)) = token.literal {
+                        self.docs.push(doc.to_string());
+                    }
+                }
+                Some(token) => return token,
+                None => {
+                    return Err(Error::ParsingError(String::from(
+                        "Token requested after end of input",
+                    )))
+                }
+            }
+        }
+    }
+
+    /// A declaration at the top level of a file, which may also import and export.
+    fn module_declaration(&mut self) -> Result<StmtId, Error> {
+        if self.find(&[&TokenType::Import])? {
+            return self.import_declaration();
+        }
+        if self.find(&[&TokenType::From])? {
+            return self.import_names_declaration();
+        }
+        if self.find(&[&TokenType::Export])? {
+            return self.export_declaration();
+        }
+        self.declaration()
+    }
+
+    fn import_declaration(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        let path = self.module_path("Expect module path after 'import'")?;
+        self.consume(TokenType::As, "Expect 'as' after module path")?;
+        let name = self.identifier("Expect module name after 'as'")?;
+        let end = self.end_statement("Expect ';' after import")?;
+        Ok(self.alloc_stmt(StmtKind::Import(path, name), start.to(end)))
+    }
+
+    fn import_names_declaration(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        let path = self.module_path("Expect module path after 'from'")?;
+        self.consume(TokenType::Import, "Expect 'import' after module path")?;
+        let mut names = Vec::new();
+        loop {
+            let name = self.identifier("Expect name to import")?;
+            if names.contains(&name) {
+                let message = format!("'{}' is imported more than once", name);
+                return Err(self.error(self.previous()?, &message));
+            }
+            names.push(name);
+            if !self.find(&[&TokenType::Comma])? {
+                break;
+            }
+        }
+        let end = self.end_statement("Expect ';' after import")?;
+        Ok(self.alloc_stmt(StmtKind::FromImport(path, names), start.to(end)))
+    }
+
+    fn module_path(&mut self, message: &str) -> Result<Rc<str>, Error> {
+        let token = self.consume(TokenType::String, message)?;
+        match &token.literal {
+            Some(Literal::Str(path)) => Ok(Rc::from(path.as_ref())),
+            _ => Err(self.error(self.previous()?, message)),
+        }
+    }
+
+    fn export_declaration(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        let declaration = if self.find(&[&TokenType::Var])? {
+            self.var_declaration()?
+        } else if self.find(&[&TokenType::Fun])? {
+            self.function()?
+        } else {
+            return Err(self.error(self.peek()?, "Expect 'var' or 'fun' after 'export'"));
+        };
+        let span = start.to(self.ast[declaration].span);
+        Ok(self.alloc_stmt(StmtKind::Export(declaration), span))
+    }
+
+    fn declaration(&mut self) -> Result<StmtId, Error> {
+        if self.check(TokenType::Import)
+            || self.check(TokenType::From)
+            || self.check(TokenType::Export)
+        {
+            let token = self.peek()?;
+            let message = format!("Can't use '{}' outside the top level", token.lexeme);
+            return Err(self.error(token, &message));
+        }
+        if self.find(&[&TokenType::Var])? {
+            return self.var_declaration();
+        }
+        if self.find(&[&TokenType::Fun])? {
+            return self.function();
+        }
+        self.statement()
+    }
+
+    fn function(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        let name = self.identifier("Expect function name")?;
+        self.consume(TokenType::LeftParen, "Expect '(' after function name")?;
+        let mut params = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                params.push(self.pattern("Expect parameter name", false)?);
+                if !self.find(&[&TokenType::Comma])? {
+                    break;
+                }
+            }
+        }
+        self.check_names(&params)?;
+        self.consume(TokenType::RightParen, "Expect ')' after parameters")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body")?;
+        // A loop around the declaration doesn't reach into the body
+        self.functions += 1;
+        let loops = std::mem::take(&mut self.loops);
+        let labels = std::mem::take(&mut self.labels);
+        let body = self.block_statements();
+        self.loops = loops;
+        self.labels = labels;
+        self.functions -= 1;
+        let (body, end) = body?;
+        Ok(self.alloc_stmt(StmtKind::Function(name, params, body), start.to(end)))
+    }
+
+    fn var_declaration(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        let pattern = self.pattern("Expect variable name", false)?;
+        self.check_names(std::slice::from_ref(&pattern))?;
+        let initializer = if self.find(&[&TokenType::Equal])? {
+            Some(self.expression()?)
+        } else if let Pattern::Name(_) = pattern {
+            None
+        } else {
+            return Err(self.error(self.peek()?, "Expect '=' after destructuring pattern"));
+        };
+        let end = self.end_statement("Expect ';' after variable declaration")?;
+        Ok(self.alloc_stmt(StmtKind::Var(pattern, initializer), start.to(end)))
+    }
+
+    /// A pattern, see pattern.rs for the grammar. `message` is the error for a missing name,
+    /// and `refutable` allows the patterns of match arms that not every value matches.
+    fn pattern(&mut self, message: &str, refutable: bool) -> Result<Pattern, Error> {
+        if refutable {
+            if let Some(pattern) = self.refutable_pattern()? {
+                return Ok(pattern);
+            }
+        }
+        if self.find(&[&TokenType::LeftParen])? {
+            let (mut items, trailing_comma) =
+                self.pattern_items(TokenType::RightParen, refutable)?;
+            // `(a)` is just `a`
+            if items.len() == 1 && !trailing_comma && !matches!(items[0], Pattern::Rest(_)) {
+                return Ok(items.remove(0));
+            }
+            return Ok(Pattern::Tuple(items));
+        }
+        if self.find(&[&TokenType::LeftBracket])? {
+            let (items, _) = self.pattern_items(TokenType::RightBracket, refutable)?;
+            return Ok(Pattern::List(items));
+        }
+        if self.find(&[&TokenType::LeftBrace])? {
+            let mut fields = Vec::new();
+            while !self.check(TokenType::RightBrace) {
+                let key = self.identifier("Expect key name")?;
+                let pattern = if self.find(&[&TokenType::Colon])? {
+                    self.pattern(message, refutable)?
+                } else {
+                    Pattern::Name(key)
+                };
+                fields.push((key, pattern));
+                if !self.find(&[&TokenType::Comma])? {
+                    break;
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after map pattern")?;
+            return Ok(Pattern::Map(fields));
+        }
+        Ok(Pattern::Name(self.identifier(message)?))
+    }
+
+    /// `_`, a literal or a range, or `None` if the next tokens are another pattern.
+    fn refutable_pattern(&mut self) -> Result<Option<Pattern>, Error> {
+        if self.check(TokenType::Identifier) && self.peek()?.lexeme == "_" {
+            self.advance()?;
+            return Ok(Some(Pattern::Wildcard));
+        }
+        if self.check(TokenType::Minus) || self.check(TokenType::Number) {
+            let start = self.pattern_number()?;
+            if self.find(&[&TokenType::DotDot, &TokenType::DotDotEqual])? {
+                let inclusive = self.previous()?.token_type == TokenType::DotDotEqual;
+                let end = self.pattern_number()?;
+                return Ok(Some(Pattern::Range(start, end, inclusive)));
+            }
+            return Ok(Some(Pattern::Literal(LiteralOp::Number(start))));
+        }
+        let literals = [
+            &TokenType::String,
+            &TokenType::True,
+            &TokenType::False,
+            &TokenType::Nil,
+        ];
+        if self.find(&literals)? {
+            return match self.previous_literal()? {
+                Some(literal) => Ok(Some(Pattern::Literal(literal))),
+                None => Err(self.error(self.previous()?, "Invalid literal")),
+            };
+        }
+        Ok(None)
+    }
+
+    /// A number in a pattern, which may be negative.
+    fn pattern_number(&mut self) -> Result<f64, Error> {
+        let sign = if self.find(&[&TokenType::Minus])? {
+            -1.0
+        } else {
+            1.0
+        };
+        let token = self.consume(TokenType::Number, "Expect number")?;
+        match token.literal {
+            Some(Literal::Number(n)) => Ok(sign * n),
+            _ => Err(Error::ParsingError(format!(
+                "Invalid number at line {}",
+                token.span.line
+            ))),
+        }
+    }
+
+    /// The items of a tuple or list pattern up to the closing token, and whether the last
+    /// one is followed by a comma.
+    fn pattern_items(
+        &mut self,
+        close: TokenType,
+        refutable: bool,
+    ) -> Result<(Vec<Pattern>, bool), Error> {
+        let mut items = Vec::new();
+        let mut trailing_comma = false;
+        while !self.check(close) {
+            if self.find(&[&TokenType::DotDotDot])? {
+                if items.iter().any(|item| matches!(item, Pattern::Rest(_))) {
+                    return Err(
+                        self.error(self.previous()?, "Only one '...' is allowed in a pattern")
+                    );
+                }
+                items.push(Pattern::Rest(self.identifier("Expect name after '...'")?));
+            } else {
+                items.push(self.pattern("Expect variable name", refutable)?);
+            }
+            trailing_comma = self.find(&[&TokenType::Comma])?;
+            if !trailing_comma {
+                break;
+            }
+        }
+        let message = if close == TokenType::RightParen {
+            "Expect ')' after tuple pattern"
+        } else {
+            "Expect ']' after list pattern"
+        };
+        self.consume(close, message)?;
+        Ok((items, trailing_comma))
+    }
+
+    /// Rejects patterns that declare a name twice, e.g. `var (a, a) = t;`.
+    fn check_names(&self, patterns: &[Pattern]) -> Result<(), Error> {
+        let mut names = Vec::new();
+        for name in patterns.iter().flat_map(Pattern::names) {
+            if names.contains(&name) {
+                let message = format!("'{}' is declared more than once", name);
+                return Err(self.error(self.previous()?, &message));
+            }
+            names.push(name);
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<StmtId, Error> {
+        if self.find(&[&TokenType::Print])? {
+            return self.print_statement();
+        }
+        if self.find(&[&TokenType::LeftBrace])? {
+            return self.block();
+        }
+        if self.find(&[&TokenType::Return])? {
+            return self.return_statement();
+        }
+        if self.find(&[&TokenType::If])? {
+            return self.if_statement();
+        }
+        if self.find(&[&TokenType::While])? {
+            return self.while_statement();
+        }
+        if self.find(&[&TokenType::For])? {
+            return self.for_statement();
+        }
+        if self.find(&[&TokenType::Break, &TokenType::Continue])? {
+            return self.jump_statement();
+        }
+        if self.find(&[&TokenType::Throw])? {
+            return self.throw_statement();
+        }
+        if self.find(&[&TokenType::Try])? {
+            return self.try_statement();
+        }
+        self.expression_statement()
+    }
+
+    fn if_statement(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition")?;
+        let then_branch = self.statement()?;
+        let else_branch = if self.find(&[&TokenType::Else])? {
+            Some(self.statement()?)
+        } else {
+            None
+        };
+        let end = self.ast[else_branch.unwrap_or(then_branch)].span;
+        let kind = StmtKind::If(condition, then_branch, else_branch);
+        Ok(self.alloc_stmt(kind, start.to(end)))
+    }
+
+    fn while_statement(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition")?;
+        let body = self.loop_body()?;
+        let span = start.to(self.ast[body].span);
+        Ok(self.alloc_stmt(StmtKind::While(condition, body), span))
+    }
+
+    /// A C-style `for`, or a `for ... in` once the `in` shows up.
+    fn for_statement(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'")?;
+        let initializer = if self.find(&[&TokenType::Semicolon])? {
+            None
+        } else if self.find(&[&TokenType::Var])? {
+            Some(self.var_declaration()?)
+        } else {
+            let expr = self.expression()?;
+            if self.find(&[&TokenType::In])? {
+                return self.for_in_statement(start, expr);
+            }
+            let span = self.ast[expr].span;
+            let end = self.end_statement("Expect ';' after loop initializer")?;
+            Some(self.alloc_stmt(StmtKind::Expression(expr), span.to(end)))
+        };
+        let condition = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition")?;
+        let increment = if self.check(TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses")?;
+        let body = self.loop_body()?;
+        let span = start.to(self.ast[body].span);
+        let kind = StmtKind::For(initializer, condition, increment, body);
+        Ok(self.alloc_stmt(kind, span))
+    }
+
+    /// The rest of `for (variable in iterable) body`, after the `in`.
+    fn for_in_statement(&mut self, start: Span, variable: ExprId) -> Result<StmtId, Error> {
+        let pattern = match self.as_pattern(variable) {
+            Some(pattern) if !matches!(pattern, Pattern::Rest(_)) => pattern,
+            _ => return Err(self.error(self.previous()?, "Invalid loop variable before 'in'")),
+        };
+        self.check_names(std::slice::from_ref(&pattern))?;
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after loop iterable")?;
+        let body = self.loop_body()?;
+        let span = start.to(self.ast[body].span);
+        Ok(self.alloc_stmt(StmtKind::ForIn(pattern, iterable, body), span))
+    }
+
+    /// The pattern that an expression spells, e.g. `(k, v)`. The variable of a `for ... in`
+    /// is parsed as an expression, as only the `in` after it tells the loops apart.
+    fn as_pattern(&self, expr: ExprId) -> Option<Pattern> {
+        let items = |elements: &[ExprId]| {
+            let rests = elements
+                .iter()
+                .filter(|element| matches!(self.ast[**element].kind, ExprKind::Spread(_)))
+                .count();
+            if rests > 1 {
+                return None;
+            }
+            elements
+                .iter()
+                .map(|element| self.as_pattern(*element))
+                .collect::<Option<Vec<_>>>()
+        };
+        match &self.ast[expr].kind {
+            ExprKind::Variable(name) => Some(Pattern::Name(*name)),
+            ExprKind::Grouping(inner) => self.as_pattern(*inner),
+            ExprKind::Tuple(elements) => Some(Pattern::Tuple(items(elements)?)),
+            ExprKind::List(elements) => Some(Pattern::List(items(elements)?)),
+            ExprKind::Spread(inner) => match self.ast[*inner].kind {
+                ExprKind::Variable(name) => Some(Pattern::Rest(name)),
+                _ => None,
+            },
+            ExprKind::Map(entries) => entries
+                .iter()
+                .map(|(key, value)| match &self.ast[*key].kind {
+                    // Only a bare name, a quoted key is one character longer on each side
+                    ExprKind::Literal(LiteralOp::Str(name))
+                        if self.ast[*key].span.end - self.ast[*key].span.start == name.len() =>
+                    {
+                        Some((Symbol::intern(name), self.as_pattern(*value)?))
+                    }
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(Pattern::Map),
+            _ => None,
+        }
+    }
+
+    fn loop_body(&mut self) -> Result<StmtId, Error> {
+        self.loops += 1;
+        let body = self.statement();
+        self.loops -= 1;
+        body
+    }
+
+    /// `label: loop`, where `expr` is the label that was parsed as a variable.
+    fn labeled_statement(&mut self, expr: ExprId, label: Symbol) -> Result<StmtId, Error> {
+        if self.labels.contains(&label) {
+            let message = format!("Label '{}' is already used by an enclosing loop", label);
+            return Err(self.error(self.previous()?, &message));
+        }
+        let body: fn(&mut Self) -> Result<StmtId, Error> = if self.find(&[&TokenType::While])? {
+            Self::while_statement
+        } else if self.find(&[&TokenType::For])? {
+            Self::for_statement
+        } else {
+            return Err(self.error(self.peek()?, "Expect a loop after label"));
+        };
+        self.labels.push(label);
+        let body = body(self);
+        self.labels.pop();
+        let body = body?;
+        let span = self.ast[expr].span.to(self.ast[body].span);
+        Ok(self.alloc_stmt(StmtKind::Labeled(label, body), span))
+    }
+
+    /// `break` or `continue`, optionally with the label of the loop they apply to.
+    fn jump_statement(&mut self) -> Result<StmtId, Error> {
+        let keyword = self.previous()?.clone();
+        if self.loops == 0 {
+            let message = format!("Can't use '{}' outside of a loop", keyword.lexeme);
+            return Err(self.error(&keyword, &message));
+        }
+        let label = if self.check(TokenType::Identifier) {
+            let label = self.identifier("Expect label")?;
+            if !self.labels.contains(&label) {
+                let message = format!("No enclosing loop has the label '{}'", label);
+                return Err(self.error(self.previous()?, &message));
+            }
+            Some(label)
+        } else {
+            None
+        };
+        let kind = if keyword.token_type == TokenType::Break {
+            StmtKind::Break(label)
+        } else {
+            StmtKind::Continue(label)
+        };
+        let message = format!("Expect ';' after '{}'", keyword.lexeme);
+        let end = self.end_statement(&message)?;
+        Ok(self.alloc_stmt(kind, keyword.span.to(end)))
+    }
+
+    fn return_statement(&mut self) -> Result<StmtId, Error> {
+        let keyword = self.previous()?;
+        if self.functions == 0 {
+            return Err(self.error(keyword, "Can't return from top-level code"));
+        }
+        let start = keyword.span;
+        let value = if self.check(TokenType::Semicolon) || self.is_at_end() {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        let end = self.end_statement("Expect ';' after return value")?;
+        Ok(self.alloc_stmt(StmtKind::Return(value), start.to(end)))
+    }
+
+    fn print_statement(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        let value = self.expression()?;
+        let end = self.end_statement("Expect ';' after value")?;
+        Ok(self.alloc_stmt(StmtKind::Print(value), start.to(end)))
+    }
+
+    fn throw_statement(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        let value = self.expression()?;
+        let end = self.end_statement("Expect ';' after thrown value")?;
+        Ok(self.alloc_stmt(StmtKind::Throw(value), start.to(end)))
+    }
+
+    fn try_statement(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'")?;
+        let (body, mut end) = self.block_statements()?;
+        let catch = if self.find(&[&TokenType::Catch])? {
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'")?;
+            let name = self.identifier("Expect name of the caught value")?;
+            self.consume(TokenType::RightParen, "Expect ')' after name")?;
+            self.consume(TokenType::LeftBrace, "Expect '{' before catch body")?;
+            let (handler, handler_end) = self.block_statements()?;
+            end = handler_end;
+            Some((name, handler))
+        } else {
+            None
+        };
+        let finally = if self.find(&[&TokenType::Finally])? {
+            self.consume(TokenType::LeftBrace, "Expect '{' after 'finally'")?;
+            let (finally, finally_end) = self.block_statements()?;
+            end = finally_end;
+            Some(finally)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error(self.peek()?, "Expect 'catch' or 'finally' after try block"));
+        }
+        Ok(self.alloc_stmt(StmtKind::Try(body, catch, finally), start.to(end)))
+    }
+
+    fn block(&mut self) -> Result<StmtId, Error> {
+        let start = self.previous()?.span;
+        let (statements, end) = self.block_statements()?;
+        Ok(self.alloc_stmt(StmtKind::Block(statements), start.to(end)))
+    }
+
+    /// The statements after a `{` up to the closing `}`, and the span of the `}`.
+    fn block_statements(&mut self) -> Result<(Vec<StmtId>, Span), Error> {
+        let mut statements = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            statements.push(self.declaration()?);
         }
+        let end = self
+            .consume(TokenType::RightBrace, "Expect '}' after block")?
+            .span;
+        Ok((statements, end))
     }
 
-    fn expression(&self) -> Result<Box<Expr>, Error> {
-        self.equality()
+    fn expression_statement(&mut self) -> Result<StmtId, Error> {
+        let expr = self.expression()?;
+        // Only the `:` tells a label from a variable
+        if let ExprKind::Variable(label) = self.ast[expr].kind {
+            if self.find(&[&TokenType::Colon])? {
+                return self.labeled_statement(expr, label);
+            }
+        }
+        let start = self.ast[expr].span;
+        let end = self.end_statement("Expect ';' after expression")?;
+        Ok(self.alloc_stmt(StmtKind::Expression(expr), start.to(end)))
     }
 
-    fn equality(&self) -> Result<Box<Expr>, Error> {
-        let mut expr = self.comparison()?;
+    /// Consumes the `;` ending a statement. It may be left out after the last statement
+    /// of the input, which keeps REPL entries short.
+    fn end_statement(&mut self, message: &str) -> Result<Span, Error> {
+        if self.find(&[&TokenType::Semicolon])? || self.is_at_end() {
+            return Ok(self.previous()?.span);
+        }
+        Err(self.error(self.peek()?, message))
+    }
 
-        while self.find(&[&TokenType::BangEqual, &TokenType::EqualEqual]) {
-            let operator = self.previous()?;
-            let right = self.comparison()?;
-            expr = Box::new(Expr::Binary(expr, operator, right))
+    fn identifier(&mut self, message: &str) -> Result<Symbol, Error> {
+        let token = self.consume(TokenType::Identifier, message)?;
+        match token.literal {
+            Some(Literal::Identifier(name)) => Ok(name),
+            _ => Err(self.error(self.previous()?, message)),
+        }
+    }
+
+    fn expression(&mut self) -> Result<ExprId, Error> {
+        self.expression_bp(0)
+    }
+
+    /// Parses an expression whose operators all bind at least as tightly as `min_power`.
+    fn expression_bp(&mut self, min_power: u8) -> Result<ExprId, Error> {
+        let prefix = match rule(self.peek()?.token_type).prefix {
+            Some(prefix) => prefix,
+            None => return Err(self.error(self.peek()?, "Expect expression")),
+        };
+        self.advance()?;
+        let mut expr = prefix(self)?;
+
+        loop {
+            let rule = rule(self.peek()?.token_type);
+            let infix = match rule.infix {
+                Some(infix) if rule.power.0 >= min_power => infix,
+                _ => break,
+            };
+            self.advance()?;
+            expr = infix(self, expr, rule.power.1)?;
         }
 
         Ok(expr)
     }
 
-    fn find(&self, tokens: &[&TokenType]) -> bool {
+    /// Consumes the current token if it has one of the types. Fails if the scanner fails
+    /// on the token after it.
+    fn find(&mut self, tokens: &[&TokenType]) -> Result<bool, Error> {
         for ttype in tokens {
             if self.check(**ttype) {
-                self.advance();
-                return true;
+                self.advance()?;
+                return Ok(true);
             }
         }
-        false
+        Ok(false)
     }
 
     fn check(&self, token: TokenType) -> bool {
@@ -54,122 +836,683 @@ impl Parser {
         false
     }
 
-    fn advance(&self) -> Result<&Token, Error> {
+    fn advance(&mut self) -> Result<&Token<'a>, Error> {
         if !self.is_at_end() {
-            self.current.fetch_add(1, Ordering::AcqRel);
+            let next = self.pull()?;
+            self.previous = Some(std::mem::replace(&mut self.current, next));
         }
         self.previous()
     }
 
     fn is_at_end(&self) -> bool {
-        if let Ok(t) = self.peek() {
-            return t.token_type == TokenType::Eof;
+        self.current.token_type == TokenType::Eof
+    }
+
+    fn peek(&self) -> Result<&Token<'a>, Error> {
+        Ok(&self.current)
+    }
+
+    fn previous(&self) -> Result<&Token<'a>, Error> {
+        self.previous
+            .as_ref()
+            .ok_or_else(|| Error::ParsingError(String::from("No token has been consumed yet")))
+    }
+
+    fn binary(&mut self, left: ExprId, power: u8) -> Result<ExprId, Error> {
+        let operator = self.previous()?;
+        let op = match BinaryOp::from_token(operator.token_type) {
+            Some(op) => op,
+            None => return Err(self.error(operator, "Expect binary operator")),
+        };
+        let right = self.expression_bp(power)?;
+        let span = self.ast[left].span.to(self.ast[right].span);
+        Ok(self.alloc(ExprKind::Binary(left, op, right), span))
+    }
+
+    /// `and` and `or`, which only evaluate their right operand when the left one doesn't
+    /// decide the result.
+    fn logical(&mut self, left: ExprId, power: u8) -> Result<ExprId, Error> {
+        let op = if self.previous()?.token_type == TokenType::And {
+            LogicalOp::And
+        } else {
+            LogicalOp::Or
+        };
+        let right = self.expression_bp(power)?;
+        let span = self.ast[left].span.to(self.ast[right].span);
+        Ok(self.alloc(ExprKind::Logical(left, op, right), span))
+    }
+
+    /// The then branch extends to the `:` and may be any expression. The else branch
+    /// takes the right-associative binding power, so `a ? b : c ? d : e` nests to the right.
+    fn conditional(&mut self, condition: ExprId, power: u8) -> Result<ExprId, Error> {
+        let then_branch = self.expression()?;
+        self.consume(
+            TokenType::Colon,
+            "Expect ':' after then branch of conditional expression",
+        )?;
+        let else_branch = self.expression_bp(power)?;
+        let span = self.ast[condition].span.to(self.ast[else_branch].span);
+        Ok(self.alloc(
+            ExprKind::Conditional(condition, then_branch, else_branch),
+            span,
+        ))
+    }
+
+    /// Plain and compound assignment. Assignment is right-associative, so `a = b = 1`
+    /// assigns to `b` first.
+    fn assignment(&mut self, target: ExprId, power: u8) -> Result<ExprId, Error> {
+        let operator = self.previous()?;
+        let op = match operator.token_type {
+            TokenType::PlusEqual => Some(BinaryOp::Add),
+            TokenType::MinusEqual => Some(BinaryOp::Subtract),
+            TokenType::StarEqual => Some(BinaryOp::Multiply),
+            TokenType::SlashEqual => Some(BinaryOp::Divide),
+            TokenType::PercentEqual => Some(BinaryOp::Modulo),
+            _ => None,
+        };
+        // Only a plain `=` can take a value apart
+        if !self.is_assignable(target, op.is_none()) {
+            return Err(self.error(operator, "Invalid assignment target"));
         }
-        false
+        let value = self.expression_bp(power)?;
+        let span = self.ast[target].span.to(self.ast[value].span);
+        Ok(self.alloc(ExprKind::Assign(target, op, value), span))
+    }
+
+    fn prefix_increment(&mut self) -> Result<ExprId, Error> {
+        let operator = self.previous()?.clone();
+        let op = increment_op(operator.token_type);
+        // Only the target, so `++a ** 2` is `(++a) ** 2`
+        let target = self.expression_bp(POSTFIX.0)?;
+        if !self.is_assignable(target, false) {
+            let message = format!("Operand of '{}' must be a variable", operator.lexeme);
+            return Err(self.error(&operator, &message));
+        }
+        let span = operator.span.to(self.ast[target].span);
+        Ok(self.alloc(ExprKind::Increment(target, op, true), span))
+    }
+
+    fn postfix_increment(&mut self, target: ExprId, _power: u8) -> Result<ExprId, Error> {
+        let operator = self.previous()?;
+        let op = increment_op(operator.token_type);
+        if !self.is_assignable(target, false) {
+            let message = format!("Operand of '{}' must be a variable", operator.lexeme);
+            return Err(self.error(operator, &message));
+        }
+        let span = self.ast[target].span.to(operator.span);
+        Ok(self.alloc(ExprKind::Increment(target, op, false), span))
     }
 
-    fn peek(&self) -> Result<&Token, Error> {
-        let current = self.current.load(Ordering::Relaxed);
-        self.tokens
-            .get(current)
-            .ok_or_else(|| Error::ParsingError(format!("Could not get token at idx {}", current)))
+    /// Whether a value can be stored in `target`. With `destructure`, also in a tuple, list
+    /// or map literal of targets, which takes the value apart.
+    fn is_assignable(&self, target: ExprId, destructure: bool) -> bool {
+        match &self.ast[target].kind {
+            ExprKind::Variable(_) | ExprKind::Index(..) => true,
+            ExprKind::Tuple(elements) | ExprKind::List(elements) if destructure => {
+                let spread =
+                    |element: &&ExprId| matches!(self.ast[**element].kind, ExprKind::Spread(_));
+                elements.iter().filter(spread).count() <= 1
+                    && elements
+                        .iter()
+                        .all(|element| match self.ast[*element].kind {
+                            ExprKind::Spread(rest) => self.is_assignable(rest, false),
+                            _ => self.is_assignable(*element, true),
+                        })
+            }
+            ExprKind::Map(entries) if destructure => entries
+                .iter()
+                .all(|(_, value)| self.is_assignable(*value, true)),
+            _ => false,
+        }
     }
 
-    fn previous(&self) -> Result<&Token, Error> {
-        let current = self.current.load(Ordering::Relaxed);
-        self.tokens.get(current - 1).ok_or_else(|| {
-            Error::ParsingError(format!("Could not get token at idx {}", current - 1))
-        })
+    fn call(&mut self, callee: ExprId, _power: u8) -> Result<ExprId, Error> {
+        let arguments = self.elements(TokenType::RightParen)?;
+        let end = self
+            .consume(TokenType::RightParen, "Expect ')' after arguments")?
+            .span;
+        let span = self.ast[callee].span.to(end);
+        Ok(self.alloc(ExprKind::Call(callee, arguments), span))
     }
 
-    fn comparison(&self) -> Result<Box<Expr>, Error> {
-        let mut expr = self.term()?;
+    /// `xs[i]`, or a slice `xs[start:end]` where both bounds are optional.
+    fn index(&mut self, object: ExprId, _power: u8) -> Result<ExprId, Error> {
+        if self.find(&[&TokenType::Colon])? {
+            return self.slice(object, None);
+        }
+        let index = self.expression()?;
+        if self.find(&[&TokenType::Colon])? {
+            return self.slice(object, Some(index));
+        }
+        let end = self
+            .consume(TokenType::RightBracket, "Expect ']' after index")?
+            .span;
+        let span = self.ast[object].span.to(end);
+        Ok(self.alloc(ExprKind::Index(object, index), span))
+    }
+
+    fn slice(&mut self, object: ExprId, start: Option<ExprId>) -> Result<ExprId, Error> {
+        let end = if self.check(TokenType::RightBracket) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        let close = self
+            .consume(TokenType::RightBracket, "Expect ']' after slice")?
+            .span;
+        let span = self.ast[object].span.to(close);
+        Ok(self.alloc(ExprKind::Slice(object, start, end), span))
+    }
 
-        while self.find(&[
-            &TokenType::Greater,
-            &TokenType::GreaterEqual,
-            &TokenType::Less,
-            &TokenType::LessEqual,
-        ]) {
-            let operator = self.previous()?;
-            let right = self.term()?;
-            expr = Box::new(Expr::Binary(expr, operator, right));
+    fn get(&mut self, object: ExprId, _power: u8) -> Result<ExprId, Error> {
+        let name = self.identifier("Expect property name after '.'")?;
+        let span = self.ast[object].span.to(self.previous()?.span);
+        Ok(self.alloc(ExprKind::Get(object, name), span))
+    }
+
+    fn list(&mut self) -> Result<ExprId, Error> {
+        let start = self.previous()?.span;
+        let elements = self.elements(TokenType::RightBracket)?;
+        let end = self
+            .consume(TokenType::RightBracket, "Expect ']' after list elements")?
+            .span;
+        Ok(self.alloc(ExprKind::List(elements), start.to(end)))
+    }
+
+    fn map(&mut self) -> Result<ExprId, Error> {
+        let start = self.previous()?.span;
+        let mut entries = Vec::new();
+        while !self.check(TokenType::RightBrace) {
+            let key = self.expression_bp(COMMA.1)?;
+            // `{a: 1}` is shorthand for `{"a": 1}`, and `{a}` for `{"a": a}`
+            let value = if let ExprKind::Variable(name) = self.ast[key].kind {
+                self.ast.expr_mut(key).kind =
+                    ExprKind::Literal(LiteralOp::Str(self.ast.intern_str(name.as_str())));
+                if self.find(&[&TokenType::Colon])? {
+                    self.expression_bp(COMMA.1)?
+                } else {
+                    let span = self.ast[key].span;
+                    self.alloc(ExprKind::Variable(name), span)
+                }
+            } else {
+                self.consume(TokenType::Colon, "Expect ':' after map key")?;
+                self.expression_bp(COMMA.1)?
+            };
+            entries.push((key, value));
+            if !self.find(&[&TokenType::Comma])? {
+                break;
+            }
         }
+        let end = self
+            .consume(TokenType::RightBrace, "Expect '}' after map entries")?
+            .span;
+        Ok(self.alloc(ExprKind::Map(entries), start.to(end)))
+    }
 
-        Ok(expr)
+    /// `match (value) { pattern if guard => body, ... }`. An arm after one that matches
+    /// everything it would is reported as unreachable.
+    fn match_expression(&mut self) -> Result<ExprId, Error> {
+        let start = self.previous()?.span;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'match'")?;
+        let value = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after match value")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before match arms")?;
+        let mut arms: Vec<Arm> = Vec::new();
+        while !self.check(TokenType::RightBrace) {
+            let line = self.peek()?.span.line;
+            let pattern = self.pattern("Expect pattern", true)?;
+            self.check_names(std::slice::from_ref(&pattern))?;
+            let guard = if self.find(&[&TokenType::If])? {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            self.consume(TokenType::EqualGreater, "Expect '=>' after match pattern")?;
+            let body = self.expression_bp(COMMA.1)?;
+            if arms
+                .iter()
+                .any(|arm| arm.guard.is_none() && arm.pattern.covers(&pattern))
+            {
+                let warning = format!("[line {}] Warning: Unreachable match arm", line);
+                self.warnings.push(warning);
+            }
+            arms.push(Arm {
+                pattern,
+                guard,
+                body,
+            });
+            if !self.find(&[&TokenType::Comma])? {
+                break;
+            }
+        }
+        let end = self
+            .consume(TokenType::RightBrace, "Expect '}' after match arms")?
+            .span;
+        Ok(self.alloc(ExprKind::Match(value, arms), start.to(end)))
     }
 
-    fn term(&self) -> Result<Box<Expr>, Error> {
-        let mut expr = self.factor()?;
+    /// `lambda a, b: a + b`. The body runs up to the next comma.
+    fn lambda(&mut self) -> Result<ExprId, Error> {
+        let start = self.previous()?.span;
+        let mut params = Vec::new();
+        if !self.check(TokenType::Colon) {
+            loop {
+                params.push(self.pattern("Expect parameter name", false)?);
+                if !self.find(&[&TokenType::Comma])? {
+                    break;
+                }
+            }
+        }
+        self.check_names(&params)?;
+        self.consume(TokenType::Colon, "Expect ':' after lambda parameters")?;
+        let body = self.expression_bp(COMMA.1)?;
+        let span = start.to(self.ast[body].span);
+        Ok(self.alloc(ExprKind::Lambda(params, body), span))
+    }
 
-        while self.find(&[&TokenType::Minus, &TokenType::Plus]) {
-            let operator = self.previous()?;
-            let right = self.factor()?;
-            expr = Box::new(Expr::Binary(expr, operator, right));
+    /// Comma separated expressions up to the closing token, which is left for the caller.
+    /// A trailing comma is allowed.
+    fn elements(&mut self, close: TokenType) -> Result<Vec<ExprId>, Error> {
+        let mut elements = Vec::new();
+        while !self.check(close) {
+            elements.push(self.element()?);
+            if !self.find(&[&TokenType::Comma])? {
+                break;
+            }
         }
+        Ok(elements)
+    }
 
-        Ok(expr)
+    /// An element of a list, tuple or argument list, which `...` spreads.
+    fn element(&mut self) -> Result<ExprId, Error> {
+        if !self.find(&[&TokenType::DotDotDot])? {
+            return self.expression_bp(COMMA.1);
+        }
+        let start = self.previous()?.span;
+        let spread = self.expression_bp(COMMA.1)?;
+        let span = start.to(self.ast[spread].span);
+        Ok(self.alloc(ExprKind::Spread(spread), span))
     }
 
-    fn factor(&self) -> Result<Box<Expr>, Error> {
-        let mut expr = self.unary()?;
+    fn unary(&mut self) -> Result<ExprId, Error> {
+        let operator = self.previous()?;
+        let op = match operator.token_type {
+            TokenType::Minus => UnaryOp::Negate,
+            TokenType::Bang => UnaryOp::Not,
+            TokenType::Tilde => UnaryOp::BitNot,
+            _ => return Err(self.error(operator, "Expect unary operator")),
+        };
+        let start = operator.span;
+        let right = self.expression_bp(UNARY)?;
+        let span = start.to(self.ast[right].span);
+        Ok(self.alloc(ExprKind::Unary(op, right), span))
+    }
 
-        while self.find(&[&TokenType::Slash, &TokenType::Star]) {
-            let operator = self.previous().unwrap();
-            let right = self.unary()?;
-            expr = Box::new(Expr::Binary(expr, operator, right));
+    /// `(a)` groups, while `()`, `(a,)` and `(a, b)` are tuples.
+    fn grouping(&mut self) -> Result<ExprId, Error> {
+        let start = self.previous()?.span;
+        if self.find(&[&TokenType::RightParen])? {
+            let span = start.to(self.previous()?.span);
+            return Ok(self.alloc(ExprKind::Tuple(Vec::new()), span));
         }
+        let first = self.element()?;
+        let is_spread = matches!(self.ast[first].kind, ExprKind::Spread(_));
+        if !is_spread && !self.find(&[&TokenType::Comma])? {
+            let end = self.consume(TokenType::RightParen, "Expect ')' after expression")?;
+            let span = start.to(end.span);
+            return Ok(self.alloc(ExprKind::Grouping(first), span));
+        }
+        let mut elements = vec![first];
+        if !is_spread || self.find(&[&TokenType::Comma])? {
+            elements.extend(self.elements(TokenType::RightParen)?);
+        }
+        let end = self
+            .consume(TokenType::RightParen, "Expect ')' after tuple elements")?
+            .span;
+        Ok(self.alloc(ExprKind::Tuple(elements), start.to(end)))
+    }
 
-        Ok(expr)
+    fn variable(&mut self) -> Result<ExprId, Error> {
+        let token = self.previous()?;
+        let span = token.span;
+        match token.literal {
+            Some(Literal::Identifier(name)) => Ok(self.alloc(ExprKind::Variable(name), span)),
+            _ => Err(self.error(token, "Expect identifier")),
+        }
     }
 
-    fn unary(&self) -> Result<Box<Expr>, Error> {
-        while self.find(&[&TokenType::Bang, &TokenType::Minus]) {
-            let operator = self.previous().unwrap();
-            let right = self.unary()?;
-            return Ok(Box::new(Expr::Unary(operator, right)));
+    fn literal(&mut self) -> Result<ExprId, Error> {
+        let op = self.previous_literal()?;
+        let span = self.previous()?.span;
+        let op = match op {
+            Some(op) => op,
+            None => {
+                return Err(Error::ParsingError(format!(
+                    "Invalid literal at line {}",
+                    span.line
+                )))
+            }
+        };
+        Ok(self.alloc(ExprKind::Literal(op), span))
+    }
+
+    /// The value of the literal token just consumed, see `literal_op`.
+    fn previous_literal(&mut self) -> Result<Option<LiteralOp>, Error> {
+        // Borrows the fields apart, as the token is read while the `Ast` is written
+        let token = self
+            .previous
+            .as_ref()
+            .ok_or_else(|| Error::ParsingError(String::from("No token has been consumed yet")))?;
+        Ok(literal_op(token, self.ast))
+    }
+
+    fn alloc(&mut self, kind: ExprKind, span: Span) -> ExprId {
+        self.ast.alloc_expr(Expr { span, kind })
+    }
+
+    fn alloc_stmt(&mut self, kind: StmtKind, span: Span) -> StmtId {
+        self.ast.alloc_stmt(Stmt { span, kind })
+    }
+
+    fn consume(&mut self, ttype: TokenType, message: &str) -> Result<&Token<'a>, Error> {
+        if self.check(ttype) {
+            return self.advance();
+        }
+        Err(self.error(self.peek()?, message))
+    }
+
+    /// Running out of tokens is reported separately, so the REPL can ask for more input.
+    fn error(&self, token: &Token, message: &str) -> Error {
+        if token.token_type == TokenType::Eof {
+            return Error::UnexpectedEof(format!("{}", token.span.line), message.to_string());
         }
+        Error::SyntaxError(
+            format!("{}", token.span.line),
+            format!("at '{}'", token.lexeme),
+            message.to_string(),
+        )
+    }
+}
 
-        self.primary()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Printer;
+
+    fn parse(source: &str) -> Result<(Ast, Vec<StmtId>), Error> {
+        let mut ast = Ast::new();
+        let statements = Parser::new(Scanner::new(source), &mut ast)?.parse()?;
+        Ok((ast, statements))
     }
 
-    fn primary(&self) -> Result<Box<Expr>, Error> {
-        if self.find(&[&TokenType::False]) {
-            return Ok(Box::new(Expr::Literal(LiteralOp::False)));
+    /// The expression of a source that is a single expression statement.
+    fn expr(source: &str) -> (Ast, ExprId) {
+        let (ast, statements) = parse(source).unwrap();
+        match ast[statements[0]].kind {
+            StmtKind::Expression(expr) => (ast, expr),
+            ref kind => panic!("`{}` is not an expression but {:?}", source, kind),
         }
-        if self.find(&[&TokenType::True]) {
-            return Ok(Box::new(Expr::Literal(LiteralOp::True)));
+    }
+
+    /// The expression fully parenthesized in prefix form, e.g. `(+ 1 (* 2 3))`, to show
+    /// how it was grouped.
+    fn tree(source: &str) -> String {
+        let (ast, id) = expr(source);
+        sexpr(&ast, id)
+    }
+
+    fn sexpr(ast: &Ast, id: ExprId) -> String {
+        match &ast[id].kind {
+            ExprKind::Binary(left, op, right) => {
+                format!("({} {} {})", op, sexpr(ast, *left), sexpr(ast, *right))
+            }
+            ExprKind::Logical(left, op, right) => {
+                format!("({} {} {})", op, sexpr(ast, *left), sexpr(ast, *right))
+            }
+            ExprKind::Unary(op, operand) => format!("({} {})", op, sexpr(ast, *operand)),
+            ExprKind::Grouping(inner) => sexpr(ast, *inner),
+            _ => Printer::expr(ast, id),
         }
-        if self.find(&[&TokenType::Nil]) {
-            return Ok(Box::new(Expr::Literal(LiteralOp::Nil)));
+    }
+
+    fn syntax_error(source: &str) -> String {
+        match parse(source) {
+            Err(Error::SyntaxError(_, _, message)) | Err(Error::UnexpectedEof(_, message)) => {
+                message
+            }
+            other => panic!("`{}` should not parse, got {:?}", source, other.map(|_| ())),
         }
+    }
 
-        if self.find(&[&TokenType::Number, &TokenType::String]) {
-            let exp = match &self.previous()?.literal {
-                Some(Literal::Str(s)) => Ok(Box::new(Expr::Literal(LiteralOp::Str(s.to_string())))),
-                Some(Literal::Number(f)) => Ok(Box::new(Expr::Literal(LiteralOp::Number(*f)))),
-                _ => Err(Error::ParsingError(format!(
-                    "Invalid literal at token {}",
-                    self.current.load(Ordering::Relaxed)
-                ))),
-            };
-            return exp;
+    #[test]
+    fn scanner_errors_after_a_matched_token_are_reported() {
+        // The bad literal is scanned when the keyword before it is matched
+        assert_eq!(
+            syntax_error("print 1; print 12abc;"),
+            "unexpected character `a` in number: `12`"
+        );
+        assert_eq!(syntax_error("var x = \"open"), "Unterminated string");
+    }
+
+    #[test]
+    fn unary_operators_nest() {
+        let (ast, id) = expr("!-x;");
+        let ExprKind::Unary(UnaryOp::Not, operand) = ast[id].kind else {
+            panic!("expected `!`, got {:?}", ast[id].kind);
+        };
+        let ExprKind::Unary(UnaryOp::Negate, operand) = ast[operand].kind else {
+            panic!("expected `-`, got {:?}", ast[operand].kind);
+        };
+        assert!(matches!(ast[operand].kind, ExprKind::Variable(_)));
+    }
+
+    #[test]
+    fn consume_reports_the_missing_token() {
+        assert_eq!(syntax_error("(1 + 2;"), "Expect ')' after expression");
+        assert_eq!(syntax_error("(1 + 2"), "Expect ')' after expression");
+    }
+
+    #[test]
+    fn running_out_of_input_is_unexpected_eof() {
+        for source in ["(1 +", "print", "{ var x = 1;", "fun f(a,"] {
+            assert!(
+                matches!(parse(source), Err(Error::UnexpectedEof(_, _))),
+                "`{}` should end unexpectedly",
+                source
+            );
         }
+        assert!(matches!(parse("(1 + ;"), Err(Error::SyntaxError(..))));
+    }
 
-        if self.find(&[&TokenType::LeftParen]) {
-            let expr = self.expression()?;
-            self.consume(TokenType::RightParen)?;
-            return Ok(Box::new(Expr::Grouping(expr)));
+    #[test]
+    fn binary_operators_follow_precedence() {
+        assert_eq!(tree("1 + 2 * 3;"), "(+ 1 (* 2 3))");
+        assert_eq!(tree("1 * 2 + 3;"), "(+ (* 1 2) 3)");
+        assert_eq!(tree("a == b < c + d;"), "(== a (< b (+ c d)))");
+        assert_eq!(tree("-a * b;"), "(* (- a) b)");
+        assert_eq!(tree("(1 + 2) * 3;"), "(* (+ 1 2) 3)");
+    }
+
+    #[test]
+    fn binary_operators_associate_to_the_left() {
+        assert_eq!(tree("1 - 2 - 3;"), "(- (- 1 2) 3)");
+        assert_eq!(tree("a / b * c;"), "(* (/ a b) c)");
+        assert_eq!(tree("a == b != c;"), "(!= (== a b) c)");
+    }
+
+    #[test]
+    fn arithmetic_and_bitwise_operators_follow_precedence() {
+        assert_eq!(tree("2 ** 3 ** 2;"), "(** 2 (** 3 2))");
+        assert_eq!(tree("-2 ** 2;"), "(- (** 2 2))");
+        assert_eq!(tree("a * b ** c % d ~/ e;"), "(~/ (% (* a (** b c)) d) e)");
+        assert_eq!(
+            tree("a | b ^ c & d << e + f;"),
+            "(| a (^ b (& c (<< d (+ e f)))))"
+        );
+        assert_eq!(tree("a & b == c;"), "(== (& a b) c)");
+        assert_eq!(tree("~a >> 1;"), "(>> (~ a) 1)");
+    }
+
+    #[test]
+    fn logical_operators_bind_between_conditional_and_equality() {
+        assert_eq!(tree("a or b and c;"), "(or a (and b c))");
+        assert_eq!(tree("a and b or c;"), "(or (and a b) c)");
+        assert_eq!(tree("a == b and c != d;"), "(and (== a b) (!= c d))");
+        assert_eq!(tree("a or b or c;"), "(or (or a b) c)");
+        let (ast, id) = expr("a or b ? c : d and e;");
+        let ExprKind::Conditional(condition, _, else_branch) = ast[id].kind else {
+            panic!("expected a conditional, got {:?}", ast[id].kind);
+        };
+        assert_eq!(sexpr(&ast, condition), "(or a b)");
+        assert_eq!(sexpr(&ast, else_branch), "(and d e)");
+    }
+
+    #[test]
+    fn assignments_need_a_target_and_associate_to_the_right() {
+        let (ast, id) = expr("a = b += c;");
+        let ExprKind::Assign(_, None, value) = ast[id].kind else {
+            panic!("expected `=`, got {:?}", ast[id].kind);
+        };
+        assert!(matches!(
+            ast[value].kind,
+            ExprKind::Assign(_, Some(BinaryOp::Add), _)
+        ));
+        assert_eq!(syntax_error("1 = 2;"), "Invalid assignment target");
+        assert_eq!(syntax_error("a + b += 2;"), "Invalid assignment target");
+        assert_eq!(syntax_error("(a, b) += 2;"), "Invalid assignment target");
+        assert_eq!(syntax_error("++1;"), "Operand of '++' must be a variable");
+        assert_eq!(syntax_error("f()--;"), "Operand of '--' must be a variable");
+    }
+
+    #[test]
+    fn a_brace_opens_a_block_where_a_statement_starts() {
+        let (ast, statements) = parse("{ a; } var m = {a}; ({a: 1});").unwrap();
+        assert!(matches!(ast[statements[0]].kind, StmtKind::Block(_)));
+        let StmtKind::Var(_, Some(map)) = &ast[statements[1]].kind else {
+            panic!("expected a declaration, got {:?}", ast[statements[1]].kind);
+        };
+        // `{a}` is short for `{"a": a}`
+        assert_eq!(Printer::expr(&ast, *map), "{\"a\": a}");
+        assert_eq!(Printer::stmt(&ast, statements[2]), "({\"a\": 1});");
+    }
+
+    #[test]
+    fn patterns_allow_one_rest() {
+        assert!(parse("var [a, ...b] = xs; var (...c, d) = xs;").is_ok());
+        assert_eq!(
+            syntax_error("var [...a, ...b] = xs;"),
+            "Only one '...' is allowed in a pattern"
+        );
+    }
+
+    #[test]
+    fn break_and_continue_need_a_loop() {
+        assert!(syntax_error("break;").contains("outside of a loop"));
+        assert!(syntax_error("fun f() { continue; }").contains("outside of a loop"));
+        assert!(syntax_error("while (true) { fun f() { break; } }").contains("outside of a loop"));
+        assert!(parse("while (true) { if (a) break; else continue; }").is_ok());
+    }
+
+    #[test]
+    fn only_loops_can_have_a_label() {
+        assert_eq!(syntax_error("outer: print 1;"), "Expect a loop after label");
+        assert_eq!(
+            syntax_error("outer: { break outer; }"),
+            "Expect a loop after label"
+        );
+        let (ast, statements) = parse("outer: for (;;) inner: while (true) break outer;").unwrap();
+        assert!(matches!(ast[statements[0]].kind, StmtKind::Labeled(..)));
+    }
+
+    #[test]
+    fn labels_must_name_an_enclosing_loop() {
+        assert_eq!(
+            syntax_error("a: while (true) {} while (true) break a;"),
+            "No enclosing loop has the label 'a'"
+        );
+        assert_eq!(
+            syntax_error("a: while (true) a: while (true) break;"),
+            "Label 'a' is already used by an enclosing loop"
+        );
+        // A function body is outside of the loops around it
+        assert!(syntax_error("a: while (true) { fun f() { continue a; } }").contains("loop"));
+        assert!(parse("a: while (true) break a; a: while (true) break a;").is_ok());
+    }
+
+    fn warnings(source: &str) -> Vec<String> {
+        let mut ast = Ast::new();
+        let mut parser = Parser::new(Scanner::new(source), &mut ast).unwrap();
+        parser.parse().unwrap();
+        parser.warnings().to_vec()
+    }
+
+    #[test]
+    fn arms_after_one_that_matches_everything_they_would_are_unreachable() {
+        let source = "var r = match (x) {
+            0..10 => 1,
+            5 => 2,
+            n if n > 100 => 3,
+            _ => 4,
+            [a, b] => 5,
+        };";
+        assert_eq!(
+            warnings(source),
+            [
+                "[line 3] Warning: Unreachable match arm",
+                "[line 6] Warning: Unreachable match arm"
+            ]
+        );
+        // A guard may fail, so the arms after it are still reachable
+        assert!(warnings("var r = match (x) { n if n > 0 => 1, 1 => 2 };").is_empty());
+    }
+
+    #[test]
+    fn a_missing_operand_is_an_error() {
+        assert_eq!(syntax_error("1 + * 2;"), "Expect expression");
+        assert_eq!(syntax_error("* 2;"), "Expect expression");
+    }
+
+    #[test]
+    fn nodes_span_their_source() {
+        let source = "foo +\n  (bar * 2);";
+        let (ast, id) = expr(source);
+        let span = ast[id].span;
+        assert_eq!(&source[span.start..span.end], "foo +\n  (bar * 2)");
+        assert_eq!((span.line, span.column), (1, 1));
+        let ExprKind::Binary(_, BinaryOp::Add, right) = ast[id].kind else {
+            panic!("expected `+`, got {:?}", ast[id].kind);
+        };
+        let span = ast[right].span;
+        assert_eq!(&source[span.start..span.end], "(bar * 2)");
+        assert_eq!((span.line, span.column), (2, 3));
+    }
+
+    #[test]
+    fn identifiers_are_variables() {
+        let (ast, id) = expr("counter;");
+        match ast[id].kind {
+            ExprKind::Variable(name) => assert_eq!(name, Symbol::intern("counter")),
+            ref kind => panic!("expected a variable, got {:?}", kind),
         }
+    }
 
-        Err(Error::SyntaxError(
-            String::from("TODO"),
-            String::from("TODO"),
-            String::from("TODO"),
-        ))
+    #[test]
+    fn parses_a_program_of_statements() {
+        let (ast, statements) = parse("var a = 1; print a; { var b; a; }").unwrap();
+        assert!(matches!(ast[statements[0]].kind, StmtKind::Var(_, Some(_))));
+        assert!(matches!(ast[statements[1]].kind, StmtKind::Print(_)));
+        let StmtKind::Block(block) = &ast[statements[2]].kind else {
+            panic!("expected a block, got {:?}", ast[statements[2]].kind);
+        };
+        assert!(matches!(ast[block[0]].kind, StmtKind::Var(_, None)));
+        assert!(matches!(ast[block[1]].kind, StmtKind::Expression(_)));
     }
 
-    fn consume(&self, ttype: TokenType) -> Result<(), Error> {
-        Ok(())
+    #[test]
+    fn the_last_semicolon_is_optional() {
+        let (_, statements) = parse("var a = 1; print a").unwrap();
+        assert_eq!(statements.len(), 2);
+        assert_eq!(syntax_error("print a print b"), "Expect ';' after value");
+        assert_eq!(syntax_error("{ print a }"), "Expect ';' after value");
     }
 }
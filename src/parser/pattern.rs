@@ -0,0 +1,222 @@
+use crate::parser::expr::LiteralOp;
+use crate::Symbol;
+use std::fmt;
+/*
+Pattern grammar, for variable declarations and parameters:
+
+pattern        → IDENTIFIER | "(" pattern ")" | "(" items? ")" | "[" items? "]"
+               | "{" fields? "}" ;
+items          → item ( "," item )* ","? ;
+item           → pattern | "..." IDENTIFIER ;
+fields         → field ( "," field )* ","? ;
+field          → IDENTIFIER ( ":" pattern )? ;
+
+A parenthesized pattern with one item and no comma is not a tuple, like in expressions.
+At most one item of a tuple or list pattern may be a rest.
+
+The arms of a match can also use patterns that a value may fail to match:
+
+pattern        → ... | "_" | literal | number ( ".." | "..=" ) number ;
+literal        → number | STRING | "true" | "false" | "nil" ;
+number         → "-"? NUMBER ;
+
+"_" matches anything without declaring a name. "a..b" matches the numbers from a up to b,
+"a..=b" includes b.
+
+There are no patterns for class instances, like `Point(x, y)`, because Seid has no
+classes yet.
+*/
+
+/// The left hand side of a declaration. Patterns name variables and take apart the value
+/// they are matched against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Name(Symbol),
+    /// `(a, b)`, matches a tuple or list.
+    Tuple(Vec<Pattern>),
+    /// `[a, b]`, matches a tuple or list.
+    List(Vec<Pattern>),
+    /// `...rest`, the items not taken by the rest of a tuple or list pattern.
+    Rest(Symbol),
+    /// `{name, age: years}`: keys and the patterns their values are matched against.
+    Map(Vec<(Symbol, Pattern)>),
+    /// `_`, only in a match arm.
+    Wildcard,
+    /// A value that is matched by `==`, only in a match arm.
+    Literal(LiteralOp),
+    /// Start, end and whether the end is included, only in a match arm.
+    Range(f64, f64, bool),
+}
+
+impl Pattern {
+    /// The names the pattern declares, in order.
+    pub fn names(&self) -> Vec<Symbol> {
+        let mut names = Vec::new();
+        self.collect_names(&mut names);
+        names
+    }
+
+    fn collect_names(&self, names: &mut Vec<Symbol>) {
+        match self {
+            Pattern::Name(name) | Pattern::Rest(name) => names.push(*name),
+            Pattern::Tuple(items) | Pattern::List(items) => {
+                for item in items {
+                    item.collect_names(names);
+                }
+            }
+            Pattern::Map(fields) => {
+                for (_, pattern) in fields {
+                    pattern.collect_names(names);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range(..) => {}
+        }
+    }
+
+    /// Whether every value that `other` matches is matched by this pattern too, which
+    /// makes a later match arm with `other` unreachable. It may miss some cases.
+    pub fn covers(&self, other: &Pattern) -> bool {
+        match (self, other) {
+            (Pattern::Wildcard | Pattern::Name(_), _) => true,
+            (Pattern::Literal(a), Pattern::Literal(b)) => a == b,
+            (Pattern::Range(start, end, inclusive), Pattern::Literal(LiteralOp::Number(n))) => {
+                *start <= *n && (*n < *end || (*inclusive && *n == *end))
+            }
+            (Pattern::Range(start, end, inclusive), Pattern::Range(from, to, includes)) => {
+                start <= from && (to < end || (to == end && (*inclusive || !*includes)))
+            }
+            // Both match tuples and lists alike
+            (
+                Pattern::Tuple(items) | Pattern::List(items),
+                Pattern::Tuple(others) | Pattern::List(others),
+            ) => {
+                items.len() == others.len()
+                    && items
+                        .iter()
+                        .zip(others)
+                        .all(|(item, other)| match (item, other) {
+                            (Pattern::Rest(_), Pattern::Rest(_)) => true,
+                            (Pattern::Rest(_), _) | (_, Pattern::Rest(_)) => false,
+                            (item, other) => item.covers(other),
+                        })
+            }
+            // A map pattern with more keys only matches fewer maps
+            (Pattern::Map(fields), Pattern::Map(others)) => fields.iter().all(|(key, field)| {
+                others
+                    .iter()
+                    .any(|(other_key, other)| key == other_key && field.covers(other))
+            }),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Name(name) => write!(f, "{}", name),
+            Pattern::Tuple(items) => {
+                write!(f, "({}", patterns(items))?;
+                // `(a)` would be just `a`
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Pattern::List(items) => write!(f, "[{}]", patterns(items)),
+            Pattern::Rest(name) => write!(f, "...{}", name),
+            Pattern::Map(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, pattern)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match pattern {
+                        Pattern::Name(name) if name == key => write!(f, "{}", key)?,
+                        _ => write!(f, "{}: {}", key, pattern)?,
+                    }
+                }
+                write!(f, "}}")
+            }
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Literal(literal) => write!(f, "{}", literal),
+            Pattern::Range(start, end, inclusive) => write!(
+                f,
+                "{}{}{}",
+                LiteralOp::Number(*start),
+                if *inclusive { "..=" } else { ".." },
+                LiteralOp::Number(*end)
+            ),
+        }
+    }
+}
+
+/// Patterns separated by commas, e.g. parameters.
+pub fn patterns(patterns: &[Pattern]) -> String {
+    patterns
+        .iter()
+        .map(|pattern| pattern.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> Pattern {
+        Pattern::Name(Symbol::intern(name))
+    }
+
+    fn number(n: f64) -> Pattern {
+        Pattern::Literal(LiteralOp::Number(n))
+    }
+
+    #[test]
+    fn names_and_wildcards_cover_everything() {
+        for pattern in [name("x"), Pattern::Wildcard] {
+            assert!(pattern.covers(&number(1.0)));
+            assert!(pattern.covers(&Pattern::List(vec![name("a")])));
+            assert!(pattern.covers(&Pattern::Wildcard));
+        }
+        assert!(!number(1.0).covers(&Pattern::Wildcard));
+    }
+
+    #[test]
+    fn ranges_cover_the_numbers_and_ranges_inside_them() {
+        let range = Pattern::Range(0.0, 10.0, false);
+        assert!(range.covers(&number(0.0)));
+        assert!(!range.covers(&number(10.0)));
+        assert!(Pattern::Range(0.0, 10.0, true).covers(&number(10.0)));
+        assert!(range.covers(&Pattern::Range(2.0, 10.0, false)));
+        assert!(!range.covers(&Pattern::Range(2.0, 10.0, true)));
+        assert!(!range.covers(&Pattern::Range(-1.0, 5.0, false)));
+    }
+
+    #[test]
+    fn collections_cover_item_by_item() {
+        let pair = Pattern::Tuple(vec![name("a"), Pattern::Wildcard]);
+        assert!(pair.covers(&Pattern::List(vec![number(1.0), number(2.0)])));
+        assert!(!pair.covers(&Pattern::Tuple(vec![number(1.0)])));
+        let rest = Pattern::List(vec![name("a"), Pattern::Rest(Symbol::intern("r"))]);
+        assert!(!rest.covers(&pair));
+        let x = Symbol::intern("x");
+        let y = Symbol::intern("y");
+        let fewer = Pattern::Map(vec![(x, Pattern::Wildcard)]);
+        let more = Pattern::Map(vec![(x, number(1.0)), (y, name("y"))]);
+        assert!(fewer.covers(&more));
+        assert!(!more.covers(&fewer));
+    }
+
+    #[test]
+    fn displays_as_source() {
+        let x = Symbol::intern("x");
+        let pattern = Pattern::Tuple(vec![
+            Pattern::Map(vec![(x, name("x")), (Symbol::intern("y"), number(-1.5))]),
+            Pattern::Range(1.0, 2.0, true),
+            Pattern::Rest(x),
+        ]);
+        assert_eq!(pattern.to_string(), "({x, y: -1.5}, 1..=2, ...x)");
+        assert_eq!(Pattern::Tuple(vec![Pattern::Wildcard]).to_string(), "(_,)");
+    }
+}
@@ -0,0 +1,647 @@
+use crate::parser::ast::{Ast, ExprId, StmtId};
+use crate::parser::expr::{BinaryOp, ExprKind, LiteralOp, UnaryOp};
+use crate::parser::parser::expr_power;
+use crate::parser::pattern::patterns;
+use crate::parser::stmt::StmtKind;
+use crate::parser::visit::Visitor;
+use std::rc::Rc;
+
+/// Prints an AST back as Seid source that parses to the same tree.
+///
+/// Parentheses are printed only where precedence or associativity needs them, so
+/// `Grouping` nodes that don't change the parse are dropped and missing ones are added.
+pub struct Printer {
+    out: String,
+    indent: usize,
+    /// Where the last expression statement starts in `out`.
+    statement_start: Option<usize>,
+}
+
+impl Printer {
+    pub fn stmt(ast: &Ast, id: StmtId) -> String {
+        let mut printer = Printer::new();
+        printer.visit_stmt(ast, id);
+        printer.out
+    }
+
+    pub fn expr(ast: &Ast, id: ExprId) -> String {
+        let mut printer = Printer::new();
+        printer.visit_expr(ast, id);
+        printer.out
+    }
+
+    fn new() -> Self {
+        Printer {
+            out: String::new(),
+            indent: 0,
+            statement_start: None,
+        }
+    }
+
+    fn operand(&mut self, ast: &Ast, id: ExprId, parenthesize: bool) {
+        if parenthesize {
+            self.out.push('(');
+            self.visit_expr(ast, id);
+            self.out.push(')');
+        } else {
+            self.visit_expr(ast, id);
+        }
+    }
+
+    /// An operand on the left keeps its shape unless it binds looser on its right than
+    /// its parent binds on its left.
+    fn left(&mut self, ast: &Ast, id: ExprId, power: u8) {
+        let id = ungrouped(ast, id);
+        let parenthesize = expr_power(&ast[id].kind).is_some_and(|child| child.1 <= power);
+        self.operand(ast, id, parenthesize);
+    }
+
+    /// And vice versa for an operand on the right.
+    fn right(&mut self, ast: &Ast, id: ExprId, power: u8) {
+        let id = ungrouped(ast, id);
+        let parenthesize = expr_power(&ast[id].kind).is_some_and(|child| child.0 < power);
+        self.operand(ast, id, parenthesize);
+    }
+
+    /// An argument, list element or lambda body, where only a comma needs parentheses.
+    fn element(&mut self, ast: &Ast, id: ExprId) {
+        let id = ungrouped(ast, id);
+        let parenthesize = matches!(ast[id].kind, ExprKind::Binary(_, BinaryOp::Comma, _));
+        self.operand(ast, id, parenthesize);
+    }
+
+    fn elements(&mut self, ast: &Ast, ids: &[ExprId]) {
+        for (i, id) in ids.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.element(ast, *id);
+        }
+    }
+
+    fn block(&mut self, ast: &Ast, stmts: &[StmtId]) {
+        if stmts.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push('{');
+        self.indent += 1;
+        for stmt in stmts {
+            self.out.push('\n');
+            self.out.push_str(&"    ".repeat(self.indent));
+            self.visit_stmt(ast, *stmt);
+        }
+        self.indent -= 1;
+        self.out.push('\n');
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push('}');
+    }
+}
+
+/// The expression under any number of grouping parentheses.
+fn ungrouped(ast: &Ast, mut id: ExprId) -> ExprId {
+    while let ExprKind::Grouping(inner) = ast[id].kind {
+        id = inner;
+    }
+    id
+}
+
+/// Whether the statement ends with an `if` without an `else`, which would take an `else`
+/// printed after the statement.
+fn dangles(ast: &Ast, id: StmtId) -> bool {
+    match &ast[id].kind {
+        StmtKind::If(_, _, None) => true,
+        StmtKind::If(_, _, Some(body))
+        | StmtKind::While(_, body)
+        | StmtKind::For(.., body)
+        | StmtKind::ForIn(_, _, body)
+        | StmtKind::Labeled(_, body) => dangles(ast, *body),
+        _ => false,
+    }
+}
+
+impl Visitor for Printer {
+    fn visit_stmt(&mut self, ast: &Ast, id: StmtId) {
+        match &ast[id].kind {
+            StmtKind::Expression(expr) => {
+                self.statement_start = Some(self.out.len());
+                self.visit_expr(ast, *expr);
+                self.out.push(';');
+            }
+            StmtKind::Print(expr) => {
+                self.out.push_str("print ");
+                self.visit_expr(ast, *expr);
+                self.out.push(';');
+            }
+            StmtKind::Var(pattern, initializer) => {
+                self.out.push_str("var ");
+                self.out.push_str(&pattern.to_string());
+                if let Some(expr) = initializer {
+                    self.out.push_str(" = ");
+                    self.visit_expr(ast, *expr);
+                }
+                self.out.push(';');
+            }
+            StmtKind::Function(name, params, body) => {
+                self.out
+                    .push_str(&format!("fun {}({}) ", name, patterns(params)));
+                self.block(ast, body);
+            }
+            StmtKind::Return(value) => {
+                self.out.push_str("return");
+                if let Some(expr) = value {
+                    self.out.push(' ');
+                    self.visit_expr(ast, *expr);
+                }
+                self.out.push(';');
+            }
+            StmtKind::Block(stmts) => self.block(ast, stmts),
+            StmtKind::Throw(value) => {
+                self.out.push_str("throw ");
+                self.visit_expr(ast, *value);
+                self.out.push(';');
+            }
+            StmtKind::Try(body, catch, finally) => {
+                self.out.push_str("try ");
+                self.block(ast, body);
+                if let Some((name, handler)) = catch {
+                    self.out.push_str(&format!(" catch ({}) ", name));
+                    self.block(ast, handler);
+                }
+                if let Some(finally) = finally {
+                    self.out.push_str(" finally ");
+                    self.block(ast, finally);
+                }
+            }
+            StmtKind::If(condition, then_branch, else_branch) => {
+                self.out.push_str("if (");
+                self.visit_expr(ast, *condition);
+                self.out.push_str(") ");
+                match else_branch {
+                    Some(else_branch) => {
+                        // Or the `else` would go to the `if` that ends the then branch
+                        if dangles(ast, *then_branch) {
+                            self.block(ast, &[*then_branch]);
+                        } else {
+                            self.visit_stmt(ast, *then_branch);
+                        }
+                        self.out.push_str(" else ");
+                        self.visit_stmt(ast, *else_branch);
+                    }
+                    None => self.visit_stmt(ast, *then_branch),
+                }
+            }
+            StmtKind::While(condition, body) => {
+                self.out.push_str("while (");
+                self.visit_expr(ast, *condition);
+                self.out.push_str(") ");
+                self.visit_stmt(ast, *body);
+            }
+            StmtKind::For(initializer, condition, increment, body) => {
+                self.out.push_str("for (");
+                match initializer {
+                    Some(initializer) => self.visit_stmt(ast, *initializer),
+                    None => self.out.push(';'),
+                }
+                if let Some(condition) = condition {
+                    self.out.push(' ');
+                    self.visit_expr(ast, *condition);
+                }
+                self.out.push(';');
+                if let Some(increment) = increment {
+                    self.out.push(' ');
+                    self.visit_expr(ast, *increment);
+                }
+                self.out.push_str(") ");
+                self.visit_stmt(ast, *body);
+            }
+            StmtKind::ForIn(pattern, iterable, body) => {
+                self.out.push_str(&format!("for ({} in ", pattern));
+                self.visit_expr(ast, *iterable);
+                self.out.push_str(") ");
+                self.visit_stmt(ast, *body);
+            }
+            StmtKind::Labeled(label, body) => {
+                self.out.push_str(&format!("{}: ", label));
+                self.visit_stmt(ast, *body);
+            }
+            StmtKind::Import(path, name) => {
+                let path = LiteralOp::Str(Rc::clone(path));
+                self.out.push_str(&format!("import {} as {};", path, name));
+            }
+            StmtKind::FromImport(path, names) => {
+                let path = LiteralOp::Str(Rc::clone(path));
+                let names: Vec<_> = names.iter().map(|name| name.as_str()).collect();
+                self.out
+                    .push_str(&format!("from {} import {};", path, names.join(", ")));
+            }
+            StmtKind::Export(declaration) => {
+                self.out.push_str("export ");
+                self.visit_stmt(ast, *declaration);
+            }
+            StmtKind::Break(label) | StmtKind::Continue(label) => {
+                let keyword = match ast[id].kind {
+                    StmtKind::Break(_) => "break",
+                    _ => "continue",
+                };
+                self.out.push_str(keyword);
+                if let Some(label) = label {
+                    self.out.push_str(&format!(" {}", label));
+                }
+                self.out.push(';');
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+        let id = ungrouped(ast, id);
+        let (left_power, right_power) = expr_power(&ast[id].kind).unwrap_or((0, 0));
+        match &ast[id].kind {
+            ExprKind::Literal(op) => self.out.push_str(&op.to_string()),
+            ExprKind::Variable(name) => self.out.push_str(name.as_str()),
+            ExprKind::Grouping(_) => unreachable!("groupings are skipped"),
+            ExprKind::Unary(op, operand) => {
+                self.out.push_str(&op.to_string());
+                // Keep `- -x` from turning into a `--` token
+                let operand_kind = &ast[ungrouped(ast, *operand)].kind;
+                if *op == UnaryOp::Negate
+                    && matches!(
+                        operand_kind,
+                        ExprKind::Unary(UnaryOp::Negate, _)
+                            | ExprKind::Increment(_, BinaryOp::Subtract, true)
+                    )
+                {
+                    self.out.push(' ');
+                }
+                self.right(ast, *operand, right_power);
+            }
+            ExprKind::Binary(left, op, right) => {
+                self.left(ast, *left, left_power);
+                if *op == BinaryOp::Comma {
+                    self.out.push_str(", ");
+                } else {
+                    self.out.push_str(&format!(" {} ", op));
+                }
+                self.right(ast, *right, right_power);
+            }
+            ExprKind::Logical(left, op, right) => {
+                self.left(ast, *left, left_power);
+                self.out.push_str(&format!(" {} ", op));
+                self.right(ast, *right, right_power);
+            }
+            ExprKind::Assign(target, op, value) => {
+                self.left(ast, *target, left_power);
+                match op {
+                    Some(op) => self.out.push_str(&format!(" {}= ", op)),
+                    None => self.out.push_str(" = "),
+                }
+                self.right(ast, *value, right_power);
+            }
+            ExprKind::Increment(target, op, prefix) => {
+                let operator = if *op == BinaryOp::Add { "++" } else { "--" };
+                if *prefix {
+                    self.out.push_str(operator);
+                    self.visit_expr(ast, *target);
+                } else {
+                    self.left(ast, *target, left_power);
+                    self.out.push_str(operator);
+                }
+            }
+            ExprKind::Conditional(condition, then_branch, else_branch) => {
+                self.left(ast, *condition, left_power);
+                self.out.push_str(" ? ");
+                // Anything goes between `?` and `:`
+                self.visit_expr(ast, *then_branch);
+                self.out.push_str(" : ");
+                self.right(ast, *else_branch, right_power);
+            }
+            ExprKind::Call(callee, arguments) => {
+                self.left(ast, *callee, left_power);
+                self.out.push('(');
+                self.elements(ast, arguments);
+                self.out.push(')');
+            }
+            ExprKind::Index(object, index) => {
+                self.left(ast, *object, left_power);
+                self.out.push('[');
+                self.visit_expr(ast, *index);
+                self.out.push(']');
+            }
+            ExprKind::Slice(object, start, end) => {
+                self.left(ast, *object, left_power);
+                self.out.push('[');
+                if let Some(start) = start {
+                    self.visit_expr(ast, *start);
+                }
+                self.out.push(':');
+                if let Some(end) = end {
+                    self.visit_expr(ast, *end);
+                }
+                self.out.push(']');
+            }
+            ExprKind::Get(object, name) => {
+                self.left(ast, *object, left_power);
+                self.out.push('.');
+                self.out.push_str(name.as_str());
+            }
+            ExprKind::List(elements) => {
+                self.out.push('[');
+                self.elements(ast, elements);
+                self.out.push(']');
+            }
+            ExprKind::Tuple(elements) => {
+                self.out.push('(');
+                self.elements(ast, elements);
+                // `(a)` would be a grouping
+                if elements.len() == 1 && !matches!(ast[elements[0]].kind, ExprKind::Spread(_)) {
+                    self.out.push(',');
+                }
+                self.out.push(')');
+            }
+            ExprKind::Spread(spread) => {
+                self.out.push_str("...");
+                self.element(ast, *spread);
+            }
+            ExprKind::Map(entries) => {
+                // A statement that starts with `{` would be a block
+                let opens_statement = self.statement_start == Some(self.out.len());
+                if opens_statement {
+                    self.out.push('(');
+                }
+                self.out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    // A bare name would be read back as a string
+                    if let ExprKind::Variable(_) = ast[ungrouped(ast, *key)].kind {
+                        self.operand(ast, ungrouped(ast, *key), true);
+                    } else {
+                        self.element(ast, *key);
+                    }
+                    self.out.push_str(": ");
+                    self.element(ast, *value);
+                }
+                self.out.push('}');
+                if opens_statement {
+                    self.out.push(')');
+                }
+            }
+            ExprKind::Match(value, arms) => {
+                self.out.push_str("match (");
+                self.visit_expr(ast, *value);
+                self.out.push_str(") {");
+                if arms.is_empty() {
+                    self.out.push('}');
+                    return;
+                }
+                // One arm per line, like the statements of a block
+                self.indent += 1;
+                for arm in arms {
+                    self.out.push('\n');
+                    self.out.push_str(&"    ".repeat(self.indent));
+                    self.out.push_str(&arm.pattern.to_string());
+                    if let Some(guard) = arm.guard {
+                        self.out.push_str(" if ");
+                        self.visit_expr(ast, guard);
+                    }
+                    self.out.push_str(" => ");
+                    self.element(ast, arm.body);
+                    self.out.push(',');
+                }
+                self.indent -= 1;
+                self.out.push('\n');
+                self.out.push_str(&"    ".repeat(self.indent));
+                self.out.push('}');
+            }
+            ExprKind::Lambda(params, body) => {
+                self.out.push_str("lambda");
+                if !params.is_empty() {
+                    self.out.push(' ');
+                    self.out.push_str(&patterns(params));
+                }
+                self.out.push_str(": ");
+                self.element(ast, *body);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::visit::{walk_expr, walk_stmt};
+    use crate::parser::Parser;
+    use crate::Scanner;
+
+    /// The nodes of a tree in preorder with the ids of their children left out, so two
+    /// trees have the same shape exactly when they have the same nodes in the same places,
+    /// whatever their ids and spans. Groupings are skipped, the printer is free to drop
+    /// and add them.
+    #[derive(Default)]
+    struct Shape(Vec<String>);
+
+    impl Visitor for Shape {
+        fn visit_stmt(&mut self, ast: &Ast, id: StmtId) {
+            self.0.push(without_ids(&format!("{:?}", ast[id].kind)));
+            walk_stmt(self, ast, id);
+        }
+
+        fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+            if !matches!(ast[id].kind, ExprKind::Grouping(_)) {
+                self.0.push(without_ids(&format!("{:?}", ast[id].kind)));
+            }
+            walk_expr(self, ast, id);
+        }
+    }
+
+    /// `ExprId(3)` becomes `ExprId`, and the same for `StmtId`.
+    fn without_ids(debug: &str) -> String {
+        let mut out = String::new();
+        let mut rest = debug;
+        while let Some(i) = rest.find("Id(") {
+            out.push_str(&rest[..i + 2]);
+            rest = &rest[i + 3..];
+            rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+            rest = rest.strip_prefix(')').unwrap_or(rest);
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn shape(ast: &Ast, statements: &[StmtId]) -> Vec<String> {
+        let mut shape = Shape::default();
+        for &id in statements {
+            shape.visit_stmt(ast, id);
+        }
+        shape.0
+    }
+
+    fn parse(source: &str) -> (Ast, Vec<StmtId>) {
+        let mut ast = Ast::new();
+        let statements = Parser::new(Scanner::new(source), &mut ast)
+            .and_then(|mut parser| parser.parse())
+            .unwrap_or_else(|e| panic!("`{}` should parse: {:?}", source, e));
+        (ast, statements)
+    }
+
+    fn print(ast: &Ast, statements: &[StmtId]) -> String {
+        let lines: Vec<String> = statements
+            .iter()
+            .map(|&id| Printer::stmt(ast, id))
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Checks that the printed program parses back to the tree it was printed from.
+    fn assert_round_trips(source: &str) {
+        let (ast, statements) = parse(source);
+        let printed = print(&ast, &statements);
+        let (reparsed, restatements) = parse(&printed);
+        assert_eq!(
+            shape(&ast, &statements),
+            shape(&reparsed, &restatements),
+            "`{}` printed as `{}`",
+            source,
+            printed
+        );
+    }
+
+    /// A xorshift generator, so every run tries the same programs.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    const BINARY: &[&str] = &[
+        "+", "-", "*", "/", "%", "~/", "**", "&", "|", "^", "<<", ">>", "==", "!=", "<", "<=", ">",
+        ">=", "and", "or",
+    ];
+    const NAMES: &[&str] = &["a", "b", "c", "xs"];
+
+    /// A random expression that may stand anywhere an assignment can. Operators are
+    /// written without parentheses, so the tree comes from the parser's precedence rules.
+    fn expression(rng: &mut Rng, depth: usize) -> String {
+        if depth == 0 {
+            return match rng.below(5) {
+                0 => rng.pick(&["0", "1.5", "42", "0.25"]).to_string(),
+                1 => rng
+                    .pick(&["\"\"", "\"a \\\"quoted\\\" word\\n\"", "\"x\""])
+                    .to_string(),
+                2 => rng.pick(&["true", "false", "nil"]).to_string(),
+                _ => rng.pick(NAMES).to_string(),
+            };
+        }
+        let mut next = || expression(rng, depth - 1);
+        let (a, b, c) = (next(), next(), next());
+        match rng.below(14) {
+            0..=2 => format!("{} {} {}", a, rng.pick(BINARY), b),
+            3 => format!("{} {}", rng.pick(&["-", "!", "~"]), a),
+            4 => format!("({})", a),
+            5 => format!("{} ? {} : {}", a, b, c),
+            6 => format!(
+                "({} {} {})",
+                rng.pick(NAMES),
+                rng.pick(&["=", "+=", "*="]),
+                a
+            ),
+            7 => format!("({})({}, {})", a, b, c),
+            8 => format!("({}).{}", a, rng.pick(NAMES)),
+            9 => format!("({})[{}]", a, b),
+            10 => format!("[{}, ...{}]", a, b),
+            11 => format!("lambda {}: {}", rng.pick(NAMES), a),
+            12 => format!("{}{}", rng.pick(NAMES), rng.pick(&["++", "--"])),
+            _ => format!("{{{}: {}, {}}}", a, b, rng.pick(NAMES)),
+        }
+    }
+
+    fn statement(rng: &mut Rng, depth: usize) -> String {
+        let e = expression(rng, depth.min(3));
+        if depth == 0 {
+            return format!("print {};", e);
+        }
+        let mut next = || statement(rng, depth - 1);
+        let (s, t) = (next(), next());
+        match rng.below(7) {
+            // A `{` that starts a statement would open a block
+            0 if e.starts_with('{') => format!("({}), {};", e, expression(rng, 2)),
+            0 => format!("{}, {};", e, expression(rng, 2)),
+            // Declarations can't be the body of an `if` or `while`
+            1 => format!("{{ var {} = {}; {} }}", rng.pick(NAMES), e, s),
+            2 => format!("if ({}) {}", e, s),
+            3 => format!("if ({}) {} else {}", e, s, t),
+            4 => format!("while ({}) {}", e, s),
+            5 => format!("{{ {} {} }}", s, t),
+            _ => format!("print {};", e),
+        }
+    }
+
+    #[test]
+    fn without_ids_keeps_the_rest() {
+        assert_eq!(
+            without_ids("Binary(ExprId(12), Add, ExprId(3))"),
+            "Binary(ExprId, Add, ExprId)"
+        );
+        assert_eq!(without_ids("Block([StmtId(0)])"), "Block([StmtId])");
+    }
+
+    #[test]
+    fn shapes_ignore_spans_and_groupings() {
+        let (a, a_statements) = parse("print  (1 + (2));");
+        let (b, b_statements) = parse("var x;\nprint 1 + 2;");
+        assert_eq!(shape(&a, &a_statements), shape(&b, &b_statements[1..]));
+        let (c, c_statements) = parse("print (1 + 2) * 3;");
+        let (d, d_statements) = parse("print 1 + 2 * 3;");
+        assert_ne!(shape(&c, &c_statements), shape(&d, &d_statements));
+    }
+
+    #[test]
+    fn prints_minimal_parentheses() {
+        let (ast, statements) = parse("print ((a + b)) * (c * d) - (-(e)) ** 2;");
+        assert_eq!(
+            print(&ast, &statements),
+            "print (a + b) * (c * d) - (-e) ** 2;"
+        );
+    }
+
+    #[test]
+    fn prefix_operators_stay_apart() {
+        assert_round_trips("print - -a, - --a, !!a, ~-a;");
+    }
+
+    #[test]
+    fn maps_that_start_a_statement_get_parentheses() {
+        let (ast, statements) = parse("({a: 1}).a; ({}), b; ({} + 1)[0];");
+        assert_eq!(
+            print(&ast, &statements),
+            "({\"a\": 1}).a;\n({}), b;\n({} + 1)[0];"
+        );
+        assert_round_trips("({}, b);");
+        assert_round_trips("(({}), b), c;");
+    }
+
+    #[test]
+    fn dangling_else_keeps_its_if() {
+        assert_round_trips("if (a) { if (b) print 1; } else print 2;");
+        assert_round_trips("if (a) if (b) print 1; else print 2;");
+    }
+
+    #[test]
+    fn printed_programs_parse_to_the_same_tree() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let source = statement(&mut rng, 3);
+            assert_round_trips(&source);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::parser::ast::{Ast, ExprId, StmtId};
+use crate::parser::pattern::Pattern;
+use crate::parser::printer::Printer;
+use crate::{Span, Symbol};
+use std::fmt;
+use std::rc::Rc;
+/*
+Statement grammar:
+
+program        → ( importDecl | exportDecl | declaration )* EOF ;
+importDecl     → "import" STRING "as" IDENTIFIER ";"
+               | "from" STRING "import" IDENTIFIER ( "," IDENTIFIER )* ";" ;
+exportDecl     → "export" ( varDecl | funDecl ) ;
+declaration    → varDecl | funDecl | statement ;
+varDecl        → "var" ( IDENTIFIER ( "=" expression )? | pattern "=" expression ) ";" ;
+funDecl        → "fun" IDENTIFIER "(" ( pattern ( "," pattern )* )? ")" block ;
+statement      → exprStmt | printStmt | returnStmt | ifStmt | whileStmt | forStmt
+               | forInStmt | labeledStmt | breakStmt | continueStmt | throwStmt
+               | tryStmt | block ;
+exprStmt       → expression ";" ;
+printStmt      → "print" expression ";" ;
+returnStmt     → "return" expression? ";" ;
+ifStmt         → "if" "(" expression ")" statement ( "else" statement )? ;
+whileStmt      → "while" "(" expression ")" statement ;
+forStmt        → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")"
+                 statement ;
+forInStmt      → "for" "(" pattern "in" expression ")" statement ;
+labeledStmt    → IDENTIFIER ":" ( whileStmt | forStmt | forInStmt ) ;
+breakStmt      → "break" IDENTIFIER? ";" ;
+continueStmt   → "continue" IDENTIFIER? ";" ;
+throwStmt      → "throw" expression ";" ;
+tryStmt        → "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+block          → "{" declaration* "}" ;
+
+The ";" of the last statement in the input may be left out. An "else" belongs to the
+nearest "if". "break" and "continue" are only allowed inside a loop, and with a label
+only inside the loop of that label. Nested loops can't have the same label. A "try" needs
+a "catch", a "finally" or both. Imports and exports are only allowed at the top level of
+a file.
+*/
+
+/// A statement node. Like expressions, statements live in an `Ast` and refer to their
+/// children by id.
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub span: Span,
+    pub kind: StmtKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    Expression(ExprId),
+    Print(ExprId),
+    /// Only a plain name may be declared without an initializer.
+    Var(Pattern, Option<ExprId>),
+    /// Name, parameters and body.
+    Function(Symbol, Vec<Pattern>, Vec<StmtId>),
+    Return(Option<ExprId>),
+    /// Condition, then branch and else branch.
+    If(ExprId, StmtId, Option<StmtId>),
+    While(ExprId, StmtId),
+    /// Initializer, condition, increment and body of a C-style `for`.
+    For(Option<StmtId>, Option<ExprId>, Option<ExprId>, StmtId),
+    /// `for (pattern in iterable) body`.
+    ForIn(Pattern, ExprId, StmtId),
+    /// A loop with a label, which `break` and `continue` can name.
+    Labeled(Symbol, StmtId),
+    /// `break` with the label of the loop to leave, or the innermost one.
+    Break(Option<Symbol>),
+    /// `continue` with the label of the loop to continue, or the innermost one.
+    Continue(Option<Symbol>),
+    Throw(ExprId),
+    /// The body, the name and body of the `catch`, and the body of the `finally`.
+    Try(
+        Vec<StmtId>,
+        Option<(Symbol, Vec<StmtId>)>,
+        Option<Vec<StmtId>>,
+    ),
+    Block(Vec<StmtId>),
+    /// `import "path" as name;`, which binds the module to the name.
+    Import(Rc<str>, Symbol),
+    /// `from "path" import a, b;`, which binds the names the module exports.
+    FromImport(Rc<str>, Vec<Symbol>),
+    /// A top-level `var` or `fun` whose names other files can import.
+    Export(StmtId),
+}
+
+/// Displays a statement of an `Ast`, see `Ast::display_stmt`.
+pub struct StmtDisplay<'a> {
+    pub ast: &'a Ast,
+    pub id: StmtId,
+}
+
+impl fmt::Display for StmtDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&Printer::stmt(self.ast, self.id))
+    }
+}
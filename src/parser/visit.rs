@@ -0,0 +1,362 @@
+use crate::parser::ast::{Ast, ExprId, StmtId};
+use crate::parser::expr::ExprKind;
+use crate::parser::stmt::StmtKind;
+
+/// Walks the AST read-only. Override the `visit_*` methods for the nodes of interest and
+/// call the matching `walk_*` function to continue into their children.
+pub trait Visitor: Sized {
+    fn visit_stmt(&mut self, ast: &Ast, id: StmtId) {
+        walk_stmt(self, ast, id)
+    }
+
+    fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+        walk_expr(self, ast, id)
+    }
+}
+
+pub fn walk_stmt<V: Visitor>(visitor: &mut V, ast: &Ast, id: StmtId) {
+    match &ast[id].kind {
+        StmtKind::Expression(expr) | StmtKind::Print(expr) => visitor.visit_expr(ast, *expr),
+        StmtKind::Var(_, initializer) => {
+            if let Some(expr) = initializer {
+                visitor.visit_expr(ast, *expr)
+            }
+        }
+        StmtKind::Block(stmts) => {
+            for stmt in stmts {
+                visitor.visit_stmt(ast, *stmt)
+            }
+        }
+        StmtKind::Function(_, _, body) => {
+            for stmt in body {
+                visitor.visit_stmt(ast, *stmt)
+            }
+        }
+        StmtKind::Return(value) => {
+            if let Some(expr) = value {
+                visitor.visit_expr(ast, *expr)
+            }
+        }
+        StmtKind::If(condition, then_branch, else_branch) => {
+            visitor.visit_expr(ast, *condition);
+            visitor.visit_stmt(ast, *then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_stmt(ast, *else_branch)
+            }
+        }
+        StmtKind::While(condition, body) => {
+            visitor.visit_expr(ast, *condition);
+            visitor.visit_stmt(ast, *body);
+        }
+        StmtKind::For(initializer, condition, increment, body) => {
+            if let Some(initializer) = initializer {
+                visitor.visit_stmt(ast, *initializer)
+            }
+            if let Some(condition) = condition {
+                visitor.visit_expr(ast, *condition)
+            }
+            if let Some(increment) = increment {
+                visitor.visit_expr(ast, *increment)
+            }
+            visitor.visit_stmt(ast, *body);
+        }
+        StmtKind::ForIn(_, iterable, body) => {
+            visitor.visit_expr(ast, *iterable);
+            visitor.visit_stmt(ast, *body);
+        }
+        StmtKind::Labeled(_, body) | StmtKind::Export(body) => visitor.visit_stmt(ast, *body),
+        StmtKind::Throw(value) => visitor.visit_expr(ast, *value),
+        StmtKind::Try(body, catch, finally) => {
+            let handler = catch.iter().flat_map(|(_, handler)| handler);
+            for stmt in body.iter().chain(handler).chain(finally.iter().flatten()) {
+                visitor.visit_stmt(ast, *stmt)
+            }
+        }
+        StmtKind::Break(_)
+        | StmtKind::Continue(_)
+        | StmtKind::Import(..)
+        | StmtKind::FromImport(..) => {}
+    }
+}
+
+pub fn walk_expr<V: Visitor>(visitor: &mut V, ast: &Ast, id: ExprId) {
+    match &ast[id].kind {
+        ExprKind::Literal(_) | ExprKind::Variable(_) => {}
+        ExprKind::Unary(_, expr) | ExprKind::Grouping(expr) | ExprKind::Spread(expr) => {
+            visitor.visit_expr(ast, *expr)
+        }
+        ExprKind::Binary(left, _, right) | ExprKind::Logical(left, _, right) => {
+            visitor.visit_expr(ast, *left);
+            visitor.visit_expr(ast, *right);
+        }
+        ExprKind::Assign(target, _, value) => {
+            visitor.visit_expr(ast, *target);
+            visitor.visit_expr(ast, *value);
+        }
+        ExprKind::Increment(target, _, _) => visitor.visit_expr(ast, *target),
+        ExprKind::Call(callee, arguments) => {
+            visitor.visit_expr(ast, *callee);
+            for argument in arguments {
+                visitor.visit_expr(ast, *argument);
+            }
+        }
+        ExprKind::Index(object, index) => {
+            visitor.visit_expr(ast, *object);
+            visitor.visit_expr(ast, *index);
+        }
+        ExprKind::Slice(object, start, end) => {
+            visitor.visit_expr(ast, *object);
+            for bound in [start, end].into_iter().flatten() {
+                visitor.visit_expr(ast, *bound);
+            }
+        }
+        ExprKind::Get(object, _) => visitor.visit_expr(ast, *object),
+        ExprKind::List(elements) | ExprKind::Tuple(elements) => {
+            for element in elements {
+                visitor.visit_expr(ast, *element);
+            }
+        }
+        ExprKind::Map(entries) => {
+            for (key, value) in entries {
+                visitor.visit_expr(ast, *key);
+                visitor.visit_expr(ast, *value);
+            }
+        }
+        ExprKind::Lambda(_, body) => visitor.visit_expr(ast, *body),
+        ExprKind::Match(value, arms) => {
+            visitor.visit_expr(ast, *value);
+            for arm in arms {
+                if let Some(guard) = arm.guard {
+                    visitor.visit_expr(ast, guard)
+                }
+                visitor.visit_expr(ast, arm.body);
+            }
+        }
+        ExprKind::Conditional(condition, then_branch, else_branch) => {
+            visitor.visit_expr(ast, *condition);
+            visitor.visit_expr(ast, *then_branch);
+            visitor.visit_expr(ast, *else_branch);
+        }
+    }
+}
+
+/// Like `Visitor`, but with mutable access to the AST, e.g. for passes that rewrite nodes
+/// in place. Children are visited before the walk returns, so a visitor that replaces a
+/// node's kind after walking it sees already rewritten children.
+pub trait MutVisitor: Sized {
+    fn visit_stmt_mut(&mut self, ast: &mut Ast, id: StmtId) {
+        walk_stmt_mut(self, ast, id)
+    }
+
+    fn visit_expr_mut(&mut self, ast: &mut Ast, id: ExprId) {
+        walk_expr_mut(self, ast, id)
+    }
+}
+
+pub fn walk_stmt_mut<V: MutVisitor>(visitor: &mut V, ast: &mut Ast, id: StmtId) {
+    match ast[id].kind.clone() {
+        StmtKind::Expression(expr) | StmtKind::Print(expr) => visitor.visit_expr_mut(ast, expr),
+        StmtKind::Var(_, initializer) => {
+            if let Some(expr) = initializer {
+                visitor.visit_expr_mut(ast, expr)
+            }
+        }
+        StmtKind::Block(stmts) => {
+            for stmt in stmts {
+                visitor.visit_stmt_mut(ast, stmt)
+            }
+        }
+        StmtKind::Function(_, _, body) => {
+            for stmt in body {
+                visitor.visit_stmt_mut(ast, stmt)
+            }
+        }
+        StmtKind::Return(value) => {
+            if let Some(expr) = value {
+                visitor.visit_expr_mut(ast, expr)
+            }
+        }
+        StmtKind::If(condition, then_branch, else_branch) => {
+            visitor.visit_expr_mut(ast, condition);
+            visitor.visit_stmt_mut(ast, then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_stmt_mut(ast, else_branch)
+            }
+        }
+        StmtKind::While(condition, body) => {
+            visitor.visit_expr_mut(ast, condition);
+            visitor.visit_stmt_mut(ast, body);
+        }
+        StmtKind::For(initializer, condition, increment, body) => {
+            if let Some(initializer) = initializer {
+                visitor.visit_stmt_mut(ast, initializer)
+            }
+            if let Some(condition) = condition {
+                visitor.visit_expr_mut(ast, condition)
+            }
+            if let Some(increment) = increment {
+                visitor.visit_expr_mut(ast, increment)
+            }
+            visitor.visit_stmt_mut(ast, body);
+        }
+        StmtKind::ForIn(_, iterable, body) => {
+            visitor.visit_expr_mut(ast, iterable);
+            visitor.visit_stmt_mut(ast, body);
+        }
+        StmtKind::Labeled(_, body) | StmtKind::Export(body) => visitor.visit_stmt_mut(ast, body),
+        StmtKind::Throw(value) => visitor.visit_expr_mut(ast, value),
+        StmtKind::Try(body, catch, finally) => {
+            let handler = catch.iter().flat_map(|(_, handler)| handler);
+            for stmt in body.iter().chain(handler).chain(finally.iter().flatten()) {
+                visitor.visit_stmt_mut(ast, *stmt)
+            }
+        }
+        StmtKind::Break(_)
+        | StmtKind::Continue(_)
+        | StmtKind::Import(..)
+        | StmtKind::FromImport(..) => {}
+    }
+}
+
+pub fn walk_expr_mut<V: MutVisitor>(visitor: &mut V, ast: &mut Ast, id: ExprId) {
+    match ast[id].kind.clone() {
+        ExprKind::Literal(_) | ExprKind::Variable(_) => {}
+        ExprKind::Unary(_, expr) | ExprKind::Grouping(expr) | ExprKind::Spread(expr) => {
+            visitor.visit_expr_mut(ast, expr)
+        }
+        ExprKind::Binary(left, _, right) | ExprKind::Logical(left, _, right) => {
+            visitor.visit_expr_mut(ast, left);
+            visitor.visit_expr_mut(ast, right);
+        }
+        ExprKind::Assign(target, _, value) => {
+            visitor.visit_expr_mut(ast, target);
+            visitor.visit_expr_mut(ast, value);
+        }
+        ExprKind::Increment(target, _, _) => visitor.visit_expr_mut(ast, target),
+        ExprKind::Call(callee, arguments) => {
+            visitor.visit_expr_mut(ast, callee);
+            for argument in arguments {
+                visitor.visit_expr_mut(ast, argument);
+            }
+        }
+        ExprKind::Index(object, index) => {
+            visitor.visit_expr_mut(ast, object);
+            visitor.visit_expr_mut(ast, index);
+        }
+        ExprKind::Slice(object, start, end) => {
+            visitor.visit_expr_mut(ast, object);
+            for bound in [start, end].into_iter().flatten() {
+                visitor.visit_expr_mut(ast, bound);
+            }
+        }
+        ExprKind::Get(object, _) => visitor.visit_expr_mut(ast, object),
+        ExprKind::List(elements) | ExprKind::Tuple(elements) => {
+            for element in elements {
+                visitor.visit_expr_mut(ast, element);
+            }
+        }
+        ExprKind::Map(entries) => {
+            for (key, value) in entries {
+                visitor.visit_expr_mut(ast, key);
+                visitor.visit_expr_mut(ast, value);
+            }
+        }
+        ExprKind::Lambda(_, body) => visitor.visit_expr_mut(ast, body),
+        ExprKind::Match(value, arms) => {
+            visitor.visit_expr_mut(ast, value);
+            for arm in arms {
+                if let Some(guard) = arm.guard {
+                    visitor.visit_expr_mut(ast, guard)
+                }
+                visitor.visit_expr_mut(ast, arm.body);
+            }
+        }
+        ExprKind::Conditional(condition, then_branch, else_branch) => {
+            visitor.visit_expr_mut(ast, condition);
+            visitor.visit_expr_mut(ast, then_branch);
+            visitor.visit_expr_mut(ast, else_branch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{BinaryOp, Parser};
+    use crate::{Scanner, Symbol};
+
+    fn parse(source: &str) -> (Ast, Vec<StmtId>) {
+        let mut ast = Ast::new();
+        let statements = Parser::new(Scanner::new(source), &mut ast)
+            .unwrap()
+            .parse()
+            .unwrap();
+        (ast, statements)
+    }
+
+    /// Collects the names of the variables that are read, in the order they are visited.
+    #[derive(Default)]
+    struct Reads(Vec<Symbol>);
+
+    impl Visitor for Reads {
+        fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+            if let ExprKind::Variable(name) = ast[id].kind {
+                self.0.push(name);
+            }
+            walk_expr(self, ast, id)
+        }
+    }
+
+    /// Replaces every `+` with `-`.
+    struct Subtract;
+
+    impl MutVisitor for Subtract {
+        fn visit_expr_mut(&mut self, ast: &mut Ast, id: ExprId) {
+            if let ExprKind::Binary(_, op @ BinaryOp::Add, _) = &mut ast.expr_mut(id).kind {
+                *op = BinaryOp::Subtract;
+            }
+            walk_expr_mut(self, ast, id)
+        }
+    }
+
+    #[test]
+    fn visitor_reaches_nested_nodes_in_order() {
+        let (ast, statements) = parse("var a = b; { print c + (d * e); { f; } } g;");
+        let mut reads = Reads::default();
+        for stmt in statements {
+            reads.visit_stmt(&ast, stmt);
+        }
+        let names: Vec<&str> = reads.0.iter().map(|name| name.as_str()).collect();
+        assert_eq!(names, ["b", "c", "d", "e", "f", "g"]);
+    }
+
+    #[test]
+    fn mut_visitor_rewrites_nodes_in_place() {
+        let (mut ast, statements) = parse("print 1 + (2 + x); { var y = x + 1; }");
+        for stmt in &statements {
+            Subtract.visit_stmt_mut(&mut ast, *stmt);
+        }
+        let printed: Vec<String> = statements
+            .iter()
+            .map(|stmt| ast.display_stmt(*stmt).to_string())
+            .collect();
+        assert_eq!(printed[0], "print 1 - (2 - x);");
+        assert_eq!(printed[1], "{\n    var y = x - 1;\n}");
+    }
+
+    #[test]
+    fn default_methods_visit_every_expression() {
+        struct Count(usize);
+        impl Visitor for Count {
+            fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+                self.0 += 1;
+                walk_expr(self, ast, id)
+            }
+        }
+        let (ast, statements) = parse("print -(1 + 2) * 3;");
+        let mut count = Count(0);
+        count.visit_stmt(&ast, statements[0]);
+        // `*`, `-`, the grouping, `+` and three literals
+        assert_eq!(count.0, 7);
+    }
+}
@@ -1,63 +1,114 @@
 use crate::Error;
-use crate::{Literal, Token, TokenType};
-use std::collections::HashMap;
+use crate::Symbol;
+use crate::{Literal, Span, Token, TokenType, Trivia, TriviaKind};
+use std::borrow::Cow;
 
-pub struct Scanner {
-    source: Vec<u8>,
-    tokens: Vec<Token>,
+/// Largest integer an f64 can hold without rounding (2^53).
+const MAX_EXACT_INTEGER: u64 = 1 << 53;
+
+/// Turns source text into tokens on demand, either through `next_token` or as an `Iterator`.
+pub struct Scanner<'a> {
+    text: &'a str,
+    source: &'a [u8],
     start: usize,
     current: usize,
     line: usize,
-    keywords: HashMap<String, TokenType>,
+    // Where the current line starts, and the column of `counted` on it. Columns are counted
+    // on from the last token, so each character is counted once.
+    line_start: usize,
+    counted: usize,
+    column: usize,
+    lossless: bool,
+    trivia: Vec<Trivia<'a>>,
+    // The token produced by the last call to `scan_token`, if any
+    scanned: Option<Token<'a>>,
+    // A lossless scan runs one token ahead to know where the current token's trailing trivia ends
+    lookahead: Option<Result<Token<'a>, Error>>,
+    finished: bool,
 }
 
-impl Scanner {
-    pub fn new(source: String) -> Self {
+impl<'a> Scanner<'a> {
+    pub fn new(source: &'a str) -> Self {
         Scanner {
-            source: source.into_bytes(),
-            tokens: Vec::<Token>::new(),
+            text: source,
+            source: source.as_bytes(),
             start: 0,
             current: 0,
             line: 1,
-            keywords: vec![
-                ("and", TokenType::And),
-                ("class", TokenType::Class),
-                ("else", TokenType::Else),
-                ("false", TokenType::False),
-                ("for", TokenType::For),
-                ("fun", TokenType::Fun),
-                ("if", TokenType::If),
-                ("nil", TokenType::Nil),
-                ("or", TokenType::Or),
-                ("print", TokenType::Print),
-                ("return", TokenType::Return),
-                ("super", TokenType::Super),
-                ("this", TokenType::This),
-                ("true", TokenType::True),
-                ("var", TokenType::Var),
-                ("while", TokenType::While),
-                ("lambda", TokenType::Lambda),
-            ]
-            .into_iter()
-            .map(|(k, v)| (String::from(k), v))
-            .collect(),
+            line_start: 0,
+            counted: 0,
+            column: 1,
+            lossless: false,
+            trivia: Vec::new(),
+            scanned: None,
+            lookahead: None,
+            finished: false,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, Error> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token()?;
+    /// A scanner that keeps whitespace and comments as trivia on the tokens, so that
+    /// concatenating `Token::full_text` of every token reproduces the source byte-for-byte.
+    pub fn lossless(source: &'a str) -> Self {
+        Scanner {
+            lossless: true,
+            ..Scanner::new(source)
         }
+    }
 
-        self.tokens.push(Token {
-            token_type: TokenType::Eof,
-            lexeme: Vec::<u8>::new(),
-            literal: None,
-            line: self.line,
-        });
+    /// Scans the whole source at once.
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token<'a>>, Error> {
+        self.collect()
+    }
 
-        Ok(&self.tokens)
+    /// Scans the next token. The last token is always `Eof`, after which `None` is returned.
+    /// Scanning also stops after the first error.
+    pub fn next_token(&mut self) -> Option<Result<Token<'a>, Error>> {
+        if self.finished {
+            return None;
+        }
+        let token = match self.lookahead.take() {
+            Some(token) => token,
+            None => self.scan_next(),
+        };
+        let mut token = match token {
+            Ok(token) => token,
+            Err(e) => {
+                self.finished = true;
+                return Some(Err(e));
+            }
+        };
+        if token.token_type == TokenType::Eof {
+            self.finished = true;
+        } else if self.lossless {
+            // Trivia up to the first newline trails this token, the rest leads the next one
+            let mut next = self.scan_next();
+            if let Ok(next) = &mut next {
+                let split = next
+                    .leading_trivia
+                    .iter()
+                    .position(|t| t.kind == TriviaKind::Newline)
+                    .unwrap_or(next.leading_trivia.len());
+                token.trailing_trivia = next.leading_trivia.drain(..split).collect();
+            }
+            self.lookahead = Some(next);
+        }
+        Some(Ok(token))
+    }
+
+    fn scan_next(&mut self) -> Result<Token<'a>, Error> {
+        loop {
+            if let Some(mut token) = self.scanned.take() {
+                token.leading_trivia = std::mem::take(&mut self.trivia);
+                return Ok(token);
+            }
+            self.start = self.current;
+            self.count_column();
+            if self.is_at_end() {
+                self.add_token(TokenType::Eof);
+            } else {
+                self.scan_token()?;
+            }
+        }
     }
 
     fn is_at_end(&self) -> bool {
@@ -69,14 +120,81 @@ impl Scanner {
         match c {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
-            '{' => self.add_token(TokenType::RightBrace),
-            '}' => self.add_token(TokenType::LeftBrace),
+            '{' => self.add_token(TokenType::LeftBrace),
+            '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
+            '.' => {
+                if self.is_digit(self.peek()) {
+                    return Err(Error::SyntaxError(
+                        format!("{}", self.line),
+                        String::from("Invalid number"),
+                        String::from("numbers need a leading digit, e.g. `0.5` instead of `.5`"),
+                    ));
+                }
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    self.add_token(TokenType::DotDotDot)
+                } else if self.matches('.') {
+                    if self.matches('=') {
+                        self.add_token(TokenType::DotDotEqual)
+                    } else {
+                        self.add_token(TokenType::DotDot)
+                    }
+                } else {
+                    self.add_token(TokenType::Dot)
+                }
+            }
+            '-' => {
+                if self.matches('=') {
+                    self.add_token(TokenType::MinusEqual)
+                } else if self.matches('-') {
+                    self.add_token(TokenType::MinusMinus)
+                } else {
+                    self.add_token(TokenType::Minus)
+                }
+            }
+            '+' => {
+                if self.matches('=') {
+                    self.add_token(TokenType::PlusEqual)
+                } else if self.matches('+') {
+                    self.add_token(TokenType::PlusPlus)
+                } else {
+                    self.add_token(TokenType::Plus)
+                }
+            }
             ';' => self.add_token(TokenType::Semicolon),
-            '*' => self.add_token(TokenType::Star),
+            '*' => {
+                if self.matches('*') {
+                    self.add_token(TokenType::StarStar)
+                } else if self.matches('=') {
+                    self.add_token(TokenType::StarEqual)
+                } else {
+                    self.add_token(TokenType::Star)
+                }
+            }
+            '?' => self.add_token(TokenType::Question),
+            ':' => self.add_token(TokenType::Colon),
+            '%' => {
+                if self.matches('=') {
+                    self.add_token(TokenType::PercentEqual)
+                } else {
+                    self.add_token(TokenType::Percent)
+                }
+            }
+            '&' => self.add_token(TokenType::Ampersand),
+            '|' => self.add_token(TokenType::Pipe),
+            '^' => self.add_token(TokenType::Caret),
+            // Integer division is `~/`, as `//` starts a comment
+            '~' => {
+                if self.matches('/') {
+                    self.add_token(TokenType::TildeSlash)
+                } else {
+                    self.add_token(TokenType::Tilde)
+                }
+            }
             '!' => {
                 if self.matches('=') {
                     self.add_token(TokenType::BangEqual)
@@ -87,6 +205,8 @@ impl Scanner {
             '=' => {
                 if self.matches('=') {
                     self.add_token(TokenType::EqualEqual)
+                } else if self.matches('>') {
+                    self.add_token(TokenType::EqualGreater)
                 } else {
                     self.add_token(TokenType::Equal)
                 }
@@ -94,6 +214,8 @@ impl Scanner {
             '<' => {
                 if self.matches('=') {
                     self.add_token(TokenType::LessEqual)
+                } else if self.matches('<') {
+                    self.add_token(TokenType::LessLess)
                 } else {
                     self.add_token(TokenType::Less)
                 }
@@ -101,25 +223,37 @@ impl Scanner {
             '>' => {
                 if self.matches('=') {
                     self.add_token(TokenType::GreaterEqual)
+                } else if self.matches('>') {
+                    self.add_token(TokenType::GreaterGreater)
                 } else {
                     self.add_token(TokenType::Greater)
                 }
             }
             '/' => {
                 if self.matches('/') {
-                    while self.peek() != '\n' && !self.is_at_end() {
-                        self.advance();
-                    }
+                    self.line_comment();
+                } else if self.matches('*') {
+                    self.block_comment()?;
+                } else if self.matches('=') {
+                    self.add_token(TokenType::SlashEqual)
                 } else {
                     self.add_token(TokenType::Slash)
                 }
             }
-            ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
+            ' ' | '\r' | '\t' => {
+                while matches!(self.peek(), ' ' | '\r' | '\t') {
+                    self.advance();
+                }
+                self.add_trivia(TriviaKind::Whitespace)
+            }
+            '\n' => {
+                self.newline();
+                self.add_trivia(TriviaKind::Newline)
+            }
             '"' => self.string()?,
             _ => {
                 if self.is_digit(c) {
-                    self.number();
+                    self.number(c)?;
                 } else if self.is_alpha(c) {
                     self.identifier();
                 } else {
@@ -134,8 +268,54 @@ impl Scanner {
         Ok(())
     }
 
+    /// Skips a `//` comment, except `///` doc comments which are kept as tokens.
+    fn line_comment(&mut self) {
+        let is_doc = self.peek() == '/' && self.peek_next() != '/';
+        while self.peek() != '\n' && !self.is_at_end() {
+            self.advance();
+        }
+        if is_doc {
+            let text = &self.text[self.start + 3..self.current];
+            let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+            self.add_token_literal(
+                TokenType::DocComment,
+                Some(Literal::Str(Cow::Borrowed(text))),
+            );
+        } else {
+            self.add_trivia(TriviaKind::LineComment);
+        }
+    }
+
+    /// Skips a `/* ... */` comment. Block comments nest, so `/* a /* b */ c */` is a single comment.
+    fn block_comment(&mut self) -> Result<(), Error> {
+        let start_line = self.line;
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err(Error::UnexpectedEof(
+                    format!("{}", start_line),
+                    String::from("Unterminated block comment"),
+                ));
+            }
+            match self.advance() {
+                '/' if self.peek() == '*' => {
+                    self.advance();
+                    depth += 1;
+                }
+                '*' if self.peek() == '/' => {
+                    self.advance();
+                    depth -= 1;
+                }
+                '\n' => self.newline(),
+                _ => {}
+            }
+        }
+        self.add_trivia(TriviaKind::BlockComment);
+        Ok(())
+    }
+
     fn is_alpha(&self, c: char) -> bool {
-        (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+        c.is_ascii_alphabetic() || c == '_'
     }
 
     fn is_alphanumeric(&self, c: char) -> bool {
@@ -143,75 +323,202 @@ impl Scanner {
     }
 
     fn is_digit(&self, c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
 
     fn identifier(&mut self) {
         while self.is_alphanumeric(self.peek()) {
             self.advance();
         }
-        let text = String::from_utf8(self.source[self.start..self.current].to_vec()).unwrap();
-
-        let token_type = match self.keywords.get(&text) {
-            Some(kw_val) => *kw_val,
-            None => TokenType::Identifier,
-        };
+        let text = &self.text[self.start..self.current];
 
-        match token_type {
-            TokenType::Identifier => {
-                self.add_token_literal(token_type, Some(Literal::Identifier(text)))
-            }
-            _ => self.add_token(token_type),
+        match keyword(text) {
+            Some(kw_val) => self.add_token(kw_val),
+            None => self.add_token_literal(
+                TokenType::Identifier,
+                Some(Literal::Identifier(Symbol::intern(text))),
+            ),
         }
     }
 
-    fn number(&mut self) {
-        while self.is_digit(self.peek()) {
-            self.advance();
+    fn number(&mut self, first: char) -> Result<(), Error> {
+        if first == '0' {
+            let radix = match self.peek() {
+                'x' | 'X' => Some(16),
+                'o' | 'O' => Some(8),
+                'b' | 'B' => Some(2),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                // consume the radix prefix
+                self.advance();
+                return self.radix_number(radix);
+            }
         }
+
+        self.digits(10)?;
+        let mut is_integer = true;
         // Look for a fractional part
         if self.peek() == '.' && self.is_digit(self.peek_next()) {
             // consume the '.'
             self.advance();
-            while self.is_digit(self.peek()) {
+            self.digits(10)?;
+            is_integer = false;
+        }
+        // Look for an exponent
+        if self.peek() == 'e' || self.peek() == 'E' {
+            is_integer = false;
+            self.advance();
+            if self.peek() == '+' || self.peek() == '-' {
                 self.advance();
             }
+            if !self.is_digit(self.peek()) {
+                return Err(self.number_error("missing digits in exponent"));
+            }
+            self.digits(10)?;
         }
+        self.check_number_end()?;
 
-        let val: f64 = String::from_utf8(self.source[self.start..self.current].to_vec())
-            .unwrap()
+        let text: String = self.number_text(self.start);
+        if is_integer {
+            // Digits that don't fit a u64 are certainly too large
+            let val = text.parse().unwrap_or(u64::MAX);
+            let val = self.exact_integer(val)?;
+            self.add_token_literal(TokenType::Number, Some(Literal::Number(val)));
+            return Ok(());
+        }
+        let val: f64 = text
             .parse()
-            .unwrap();
+            .map_err(|_| self.number_error("malformed number"))?;
+        if val.is_infinite() {
+            return Err(self.number_error("number is out of range"));
+        }
+
+        self.add_token_literal(TokenType::Number, Some(Literal::Number(val)));
+        Ok(())
+    }
+
+    fn radix_number(&mut self, radix: u32) -> Result<(), Error> {
+        let digits_start = self.current;
+        if !self.peek().is_digit(radix) {
+            return Err(self.number_error("missing digits after radix prefix"));
+        }
+        self.digits(radix)?;
+        self.check_number_end()?;
+
+        let text: String = self.number_text(digits_start);
+        let val = u64::from_str_radix(&text, radix)
+            .map_err(|_| self.number_error("number is out of range"))?;
+        let val = self.exact_integer(val)?;
 
         self.add_token_literal(TokenType::Number, Some(Literal::Number(val)));
+        Ok(())
+    }
+
+    /// Every number is an f64 at runtime, so integer literals that would silently lose
+    /// precision are refused, whatever their radix.
+    fn exact_integer(&self, val: u64) -> Result<f64, Error> {
+        if val > MAX_EXACT_INTEGER {
+            return Err(self.number_error("number is too large to be represented exactly"));
+        }
+        Ok(val as f64)
+    }
+
+    /// Consumes a run of digits in the given radix, allowing single `_` separators between digits.
+    fn digits(&mut self, radix: u32) -> Result<(), Error> {
+        while self.peek().is_digit(radix) || self.peek() == '_' {
+            if self.advance() == '_' && !self.peek().is_digit(radix) {
+                return Err(self.number_error("`_` must be placed between two digits"));
+            }
+        }
+        Ok(())
+    }
+
+    /// A number running straight into letters or digits (`0b102`, `12abc`) is a typo, not two tokens.
+    fn check_number_end(&mut self) -> Result<(), Error> {
+        if self.is_alphanumeric(self.peek()) {
+            let c = self.peek();
+            return Err(self.number_error(&format!("unexpected character `{}` in number", c)));
+        }
+        Ok(())
+    }
+
+    fn number_text(&self, from: usize) -> String {
+        self.source[from..self.current]
+            .iter()
+            .map(|b| char::from(*b))
+            .filter(|c| *c != '_')
+            .collect()
+    }
+
+    fn number_error(&self, message: &str) -> Error {
+        Error::SyntaxError(
+            format!("{}", self.line),
+            String::from("Invalid number"),
+            format!(
+                "{}: `{}`",
+                message,
+                String::from_utf8_lossy(&self.source[self.start..self.current])
+            ),
+        )
     }
 
     fn string(&mut self) -> Result<(), Error> {
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            if self.advance() == '\\' && !self.is_at_end() {
+                // The escaped character can't end the string
+                self.advance();
+            }
+            if self.source[self.current - 1] == b'\n' {
+                self.newline();
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            return Err(Error::SyntaxError(
+            return Err(Error::UnexpectedEof(
                 format!("{}", self.line),
-                String::from("Parsing error"),
                 String::from("Unterminated string"),
             ));
         }
         self.advance();
 
-        self.add_token_literal(
-            TokenType::String,
-            Some(Literal::Str(
-                String::from_utf8(self.source[self.start + 1..self.current - 1].to_vec()).unwrap(),
-            )),
-        );
+        let text = &self.text[self.start + 1..self.current - 1];
+        let value = if text.contains('\\') {
+            Cow::Owned(self.unescape(text)?)
+        } else {
+            Cow::Borrowed(text)
+        };
+        self.add_token_literal(TokenType::String, Some(Literal::Str(value)));
         Ok(())
     }
 
+    fn unescape(&self, text: &str) -> Result<String, Error> {
+        let mut value = String::with_capacity(text.len());
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('r') => value.push('\r'),
+                Some('0') => value.push('\0'),
+                Some('\\') => value.push('\\'),
+                Some('"') => value.push('"'),
+                other => {
+                    return Err(Error::SyntaxError(
+                        format!("{}", self.line),
+                        String::from("Invalid string"),
+                        format!("unknown escape sequence `\\{}`", other.unwrap_or(' ')),
+                    ))
+                }
+            }
+        }
+        Ok(value)
+    }
+
     fn peek(&self) -> char {
         if self.is_at_end() {
             '\0'
@@ -243,14 +550,40 @@ impl Scanner {
         self.add_token_literal(token_type, None);
     }
 
-    fn add_token_literal(&mut self, token: TokenType, literal: Option<Literal>) {
-        let text = self.source[self.start..self.current].to_vec();
-        self.tokens.push(Token {
-            token_type: token,
-            lexeme: text,
-            literal,
+    fn add_token_literal(&mut self, token: TokenType, literal: Option<Literal<'a>>) {
+        let text = &self.text[self.start..self.current];
+        let span = Span {
+            start: self.start,
+            end: self.current,
             line: self.line,
-        });
+            column: self.column,
+        };
+        self.scanned = Some(Token::new(token, text, literal, span));
+    }
+
+    fn add_trivia(&mut self, kind: TriviaKind) {
+        if self.lossless {
+            self.trivia.push(Trivia {
+                kind,
+                text: &self.text[self.start..self.current],
+            });
+        }
+    }
+
+    /// Moves the column on to `start`. A token that spans lines keeps the column it starts at.
+    fn count_column(&mut self) {
+        if self.counted < self.line_start {
+            self.counted = self.line_start;
+            self.column = 1;
+        }
+        self.column += self.text[self.counted..self.start].chars().count();
+        self.counted = self.start;
+    }
+
+    /// Moves on to the next line, after a newline was consumed.
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
     }
 
     fn advance(&mut self) -> char {
@@ -258,3 +591,313 @@ impl Scanner {
         char::from(self.source[self.current - 1])
     }
 }
+
+/// Keywords are matched statically instead of through a map built for every scanner.
+fn keyword(text: &str) -> Option<TokenType> {
+    let token_type = match text {
+        "and" => TokenType::And,
+        "class" => TokenType::Class,
+        "else" => TokenType::Else,
+        "false" => TokenType::False,
+        "for" => TokenType::For,
+        "fun" => TokenType::Fun,
+        "if" => TokenType::If,
+        "nil" => TokenType::Nil,
+        "or" => TokenType::Or,
+        "print" => TokenType::Print,
+        "return" => TokenType::Return,
+        "super" => TokenType::Super,
+        "this" => TokenType::This,
+        "true" => TokenType::True,
+        "var" => TokenType::Var,
+        "while" => TokenType::While,
+        "lambda" => TokenType::Lambda,
+        "in" => TokenType::In,
+        "break" => TokenType::Break,
+        "continue" => TokenType::Continue,
+        "match" => TokenType::Match,
+        "throw" => TokenType::Throw,
+        "try" => TokenType::Try,
+        "catch" => TokenType::Catch,
+        "finally" => TokenType::Finally,
+        "import" => TokenType::Import,
+        "from" => TokenType::From,
+        "as" => TokenType::As,
+        "export" => TokenType::Export,
+        _ => return None,
+    };
+    Some(token_type)
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Result<Token<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(source: &str) -> Result<f64, Error> {
+        let token = Scanner::new(source).next_token().unwrap()?;
+        match token.literal {
+            Some(Literal::Number(n)) => Ok(n),
+            _ => panic!("`{}` is not a number but {:?}", source, token.token_type),
+        }
+    }
+
+    fn number_error(source: &str) -> String {
+        match number(source) {
+            Err(Error::SyntaxError(_, _, message)) => message,
+            other => panic!("`{}` should not scan, got {:?}", source, other),
+        }
+    }
+
+    #[test]
+    fn scans_decimal_numbers() {
+        assert_eq!(number("42").unwrap(), 42.0);
+        assert_eq!(number("3.25").unwrap(), 3.25);
+        assert_eq!(number("1_000_000").unwrap(), 1_000_000.0);
+        assert_eq!(number("1e3").unwrap(), 1000.0);
+        assert_eq!(number("2.5E-2").unwrap(), 0.025);
+        assert_eq!(number("1e+2").unwrap(), 100.0);
+        assert_eq!(number("9_007_199_254_740_992").unwrap(), 9007199254740992.0);
+        // Fractions and exponents are floating point, so they may round
+        assert_eq!(number("9007199254740993.0").unwrap(), 9007199254740992.0);
+        assert_eq!(number("1e20").unwrap(), 1e20);
+    }
+
+    #[test]
+    fn scans_radix_numbers() {
+        assert_eq!(number("0xff").unwrap(), 255.0);
+        assert_eq!(number("0XFF").unwrap(), 255.0);
+        assert_eq!(number("0o17").unwrap(), 15.0);
+        assert_eq!(number("0b1010_1010").unwrap(), 170.0);
+        assert_eq!(number("0x20000000000000").unwrap(), 9007199254740992.0);
+    }
+
+    #[test]
+    fn rejects_malformed_numbers() {
+        assert!(number_error("0x").contains("missing digits after radix prefix"));
+        assert!(number_error("0b102").contains("unexpected character `2`"));
+        assert!(number_error("12abc").contains("unexpected character `a`"));
+        assert!(number_error("1__0").contains("`_` must be placed between two digits"));
+        assert!(number_error("10_").contains("`_` must be placed between two digits"));
+        assert!(number_error("1e").contains("missing digits in exponent"));
+        assert!(number_error("1e999").contains("out of range"));
+        assert!(number_error("0x20000000000001").contains("too large"));
+        // Decimal integers are held to the same limit as the other radixes
+        assert!(number_error("9007199254740993").contains("too large"));
+        assert!(number_error("99999999999999999999999").contains("too large"));
+        assert!(number_error(".5").contains("leading digit"));
+    }
+
+    #[test]
+    fn a_dot_after_a_number_is_not_a_fraction() {
+        assert_eq!(
+            types("1.foo"),
+            [
+                TokenType::Number,
+                TokenType::Dot,
+                TokenType::Identifier,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    fn types(source: &str) -> Vec<TokenType> {
+        Scanner::new(source)
+            .map(|token| token.unwrap().token_type)
+            .collect()
+    }
+
+    #[test]
+    fn block_comments_nest() {
+        assert_eq!(
+            types("1 /* a /* b */ c */ 2"),
+            [TokenType::Number, TokenType::Number, TokenType::Eof]
+        );
+    }
+
+    #[test]
+    fn block_comments_count_lines() {
+        let tokens = Scanner::new("/* a\n/* b\n*/\n*/ x").scan_tokens().unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::Identifier);
+        assert_eq!(tokens[0].span.line, 4);
+    }
+
+    /// The line and column of every token but `Eof`.
+    fn positions(source: &str) -> Vec<(usize, usize)> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        tokens[..tokens.len() - 1]
+            .iter()
+            .map(|token| (token.span.line, token.span.column))
+            .collect()
+    }
+
+    #[test]
+    fn columns_count_characters_from_the_start_of_the_line() {
+        assert_eq!(positions("a  bc\n\td"), [(1, 1), (1, 4), (2, 2)]);
+        assert_eq!(positions("\"ü\" x"), [(1, 1), (1, 5)]);
+        // After a string or comment that spans lines, columns count from its last line
+        let tokens = Scanner::new("x \"a\nbc\" y").scan_tokens().unwrap();
+        assert_eq!(tokens[1].span.column, 3);
+        assert_eq!((tokens[2].span.line, tokens[2].span.column), (2, 5));
+        assert_eq!(positions("/* c\nüü */ z"), [(2, 7)]);
+        assert_eq!(positions("/// ü\nx"), [(1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn columns_match_counting_from_the_last_newline() {
+        let source =
+            "fun f(a, b) {\n  return \"ä\nö\" + /* ✓\n */ b;\n}\n\n  f(1, 2);\n".repeat(20);
+        let tokens = Scanner::new(&source).scan_tokens().unwrap();
+        for token in tokens {
+            let start = token.span.start;
+            let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+            let column = source[line_start..start].chars().count() + 1;
+            assert_eq!(token.span.column, column, "{:?}", token);
+        }
+    }
+
+    #[test]
+    fn unterminated_block_comment_is_reported_where_it_starts() {
+        match Scanner::new("x\n/* a /* b */\n\n").scan_tokens() {
+            Err(Error::UnexpectedEof(line, message)) => {
+                assert_eq!(line, "2");
+                assert_eq!(message, "Unterminated block comment");
+            }
+            other => panic!("expected an unterminated comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn doc_comments_are_tokens() {
+        let tokens = Scanner::new("/// Adds one.\n// plain\n//// not a doc\nx")
+            .scan_tokens()
+            .unwrap();
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            [TokenType::DocComment, TokenType::Identifier, TokenType::Eof]
+        );
+        match &tokens[0].literal {
+            Some(Literal::Str(doc)) => assert_eq!(doc, "Adds one."),
+            other => panic!("expected the doc text, got {:?}", other),
+        }
+    }
+
+    fn round_trip(source: &str) -> String {
+        Scanner::lossless(source)
+            .map(|token| token.unwrap().full_text())
+            .collect()
+    }
+
+    #[test]
+    fn lossless_scan_reproduces_the_source() {
+        let sources = [
+            "",
+            "   \n\n",
+            "var x = 1;   // trailing\n\n\n/* block\n comment */ print x;\n",
+            "/// doc\nfun f(a, b) {\n\treturn a + b; /* after */\n}\n// last line without newline",
+            "var s = 1;\r\n\r\nprint s;\r\n",
+            "// ÄÖÜ ß ✓ 漢字\nvar s = \"héllo wörld ✓\"; /* ünïcödé */\n",
+        ];
+        for source in sources {
+            assert_eq!(round_trip(source), source);
+        }
+    }
+
+    #[test]
+    fn trailing_trivia_ends_at_the_newline() {
+        let tokens = Scanner::lossless("a // one\n  b").scan_tokens().unwrap();
+        let trailing: Vec<&str> = tokens[0].trailing_trivia.iter().map(|t| t.text).collect();
+        let leading: Vec<&str> = tokens[1].leading_trivia.iter().map(|t| t.text).collect();
+        assert_eq!(trailing, [" ", "// one"]);
+        assert_eq!(leading, ["\n", "  "]);
+    }
+
+    #[test]
+    fn scans_on_demand_and_stops_after_an_error() {
+        let mut scanner = Scanner::new("a b $ c");
+        assert_eq!(scanner.next_token().unwrap().unwrap().lexeme, "a");
+        assert_eq!(scanner.next_token().unwrap().unwrap().lexeme, "b");
+        assert!(scanner.next_token().unwrap().is_err());
+        assert!(scanner.next_token().is_none());
+    }
+
+    #[test]
+    fn ends_with_a_single_eof() {
+        let mut scanner = Scanner::new("x");
+        assert_eq!(scanner.next_token().unwrap().unwrap().lexeme, "x");
+        let eof = scanner.next_token().unwrap().unwrap();
+        assert_eq!(eof.token_type, TokenType::Eof);
+        assert!(scanner.next_token().is_none());
+    }
+
+    #[test]
+    fn lexemes_borrow_from_the_source() {
+        let source = String::from("var name = \"text\";");
+        let range = source.as_bytes().as_ptr_range();
+        for token in Scanner::new(&source).scan_tokens().unwrap() {
+            assert!(range.contains(&token.lexeme.as_ptr()) || token.lexeme.is_empty());
+        }
+        let tokens = Scanner::new(&source).scan_tokens().unwrap();
+        let lexemes: Vec<&str> = tokens.iter().map(|t| t.lexeme).collect();
+        assert_eq!(lexemes, ["var", "name", "=", "\"text\"", ";", ""]);
+    }
+
+    #[test]
+    fn strings_borrow_their_text_unless_they_have_escapes() {
+        let tokens = Scanner::new(r#""plain" "tab\there""#)
+            .scan_tokens()
+            .unwrap();
+        match &tokens[0].literal {
+            Some(Literal::Str(Cow::Borrowed(text))) => assert_eq!(*text, "plain"),
+            other => panic!("expected borrowed text, got {:?}", other),
+        }
+        match &tokens[1].literal {
+            Some(Literal::Str(Cow::Owned(text))) => assert_eq!(text, "tab\there"),
+            other => panic!("expected unescaped text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn braces_open_and_close() {
+        assert_eq!(
+            types("{}"),
+            [TokenType::LeftBrace, TokenType::RightBrace, TokenType::Eof]
+        );
+    }
+
+    #[test]
+    fn scans_arithmetic_and_bitwise_operators() {
+        use TokenType::*;
+        assert_eq!(
+            types("% ** * ~/ ~ & | ^ << >> < >"),
+            [
+                Percent,
+                StarStar,
+                Star,
+                TildeSlash,
+                Tilde,
+                Ampersand,
+                Pipe,
+                Caret,
+                LessLess,
+                GreaterGreater,
+                Less,
+                Greater,
+                Eof
+            ]
+        );
+        // `//` still starts a comment
+        assert_eq!(
+            types("a ~/ b // c"),
+            [Identifier, TildeSlash, Identifier, Eof]
+        );
+    }
+}
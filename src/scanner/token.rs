@@ -1,35 +1,108 @@
+use crate::Symbol;
+use std::borrow::Cow;
+use std::fmt;
+
 #[derive(Debug, Clone)]
-pub enum Literal {
-    Identifier(String),
-    Str(String),
+pub enum Literal<'a> {
+    Identifier(Symbol),
+    /// The text of a string or doc comment, only owned when escapes had to be replaced.
+    Str(Cow<'a, str>),
     Number(f64),
 }
 
-#[derive(Debug)]
-pub struct Token {
+/// A token borrows its lexeme from the source, so scanning does not allocate per token.
+#[derive(Debug, Clone)]
+pub struct Token<'a> {
     pub token_type: TokenType,
-    pub lexeme: Vec<u8>,
-    pub literal: Option<Literal>,
+    pub lexeme: &'a str,
+    pub literal: Option<Literal<'a>>,
+    pub span: Span,
+    /// Whitespace and comments before the token. Only filled in by a lossless scan.
+    pub leading_trivia: Vec<Trivia<'a>>,
+    /// Whitespace and comments after the token, up to the end of its line. Only filled in by a lossless scan.
+    pub trailing_trivia: Vec<Trivia<'a>>,
+}
+
+/// A byte range in the source, along with the line and column it starts at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
     pub line: usize,
+    /// Counted in characters from 1.
+    pub column: usize,
+}
+
+impl Span {
+    /// The span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        let first = if other.start < self.start {
+            other
+        } else {
+            self
+        };
+        Span {
+            start: first.start,
+            end: self.end.max(other.end),
+            line: self.line.min(other.line),
+            column: first.column,
+        }
+    }
+}
+
+/// Source text that carries no meaning for the parser, kept around so the input can be reproduced.
+#[derive(Debug, Clone, Copy)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub text: &'a str,
 }
 
-impl Token {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+}
+
+impl<'a> Token<'a> {
     pub fn new(
         token_type: TokenType,
-        lexeme: Vec<u8>,
-        literal: Option<Literal>,
-        line: usize,
+        lexeme: &'a str,
+        literal: Option<Literal<'a>>,
+        span: Span,
     ) -> Self {
         Token {
             token_type,
             lexeme,
             literal,
-            line,
+            span,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
         }
     }
 
-    pub fn to_string(&self) -> String {
-        format!("{:?} {:?} {:?}", self.token_type, self.lexeme, self.literal)
+    /// The token's exact source text including its trivia.
+    pub fn full_text(&self) -> String {
+        let mut text = String::new();
+        for trivia in &self.leading_trivia {
+            text.push_str(trivia.text);
+        }
+        text.push_str(self.lexeme);
+        for trivia in &self.trailing_trivia {
+            text.push_str(trivia.text);
+        }
+        text
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {:?} {:?}",
+            self.token_type, self.lexeme, self.literal
+        )
     }
 }
 
@@ -40,6 +113,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -47,6 +122,12 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Question,
+    Colon,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
 
     // One or two character tokens.
     Bang,
@@ -57,11 +138,30 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    StarStar,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    PlusPlus,
+    MinusMinus,
+    Tilde,
+    TildeSlash,
+    LessLess,
+    GreaterGreater,
+    EqualGreater,
+    DotDot,
+
+    // Three character tokens.
+    DotDotDot,
+    DotDotEqual,
 
     // Literals.
     Identifier,
     String,
     Number,
+    DocComment,
 
     // Keywords
     And,
@@ -81,6 +181,41 @@ pub enum TokenType {
     Var,
     While,
     Lambda,
+    In,
+    Break,
+    Continue,
+    Match,
+    Throw,
+    Try,
+    Catch,
+    Finally,
+    Import,
+    From,
+    As,
+    Export,
 
     Eof,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn span_to_covers_both_spans_in_either_order() {
+        let first = span(2, 5, 1, 3);
+        let second = span(9, 12, 2, 4);
+        assert_eq!(first.to(second), span(2, 12, 1, 3));
+        assert_eq!(second.to(first), span(2, 12, 1, 3));
+        assert_eq!(first.to(first), first);
+    }
+}
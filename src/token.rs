@@ -1,88 +0,0 @@
-use std::fmt::Display;
-
-#[derive(Debug, Clone)]
-pub enum Literal {
-    Identifier(String),
-    Str(String),
-    Number(f64),
-}
-
-#[derive(Debug)]
-pub struct Token {
-    pub token_type: TokenType,
-    pub lexeme: Vec<u8>,
-    pub literal: Option<Literal>,
-    pub line: usize,
-}
-
-impl Token {
-    pub fn new(
-        token_type: TokenType,
-        lexeme: Vec<u8>,
-        literal: Option<Literal>,
-        line: usize,
-    ) -> Self {
-        Token {
-            token_type,
-            lexeme,
-            literal,
-            line,
-        }
-    }
-
-    pub fn to_string(&self) -> String {
-        format!("{:?} {:?} {:?}", self.token_type, self.lexeme, self.literal)
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum TokenType {
-    //Single-character tokens.
-    LeftParen,
-    RightParen,
-    LeftBrace,
-    RightBrace,
-    Comma,
-    Dot,
-    Minus,
-    Plus,
-    Semicolon,
-    Slash,
-    Star,
-
-    // One or two character tokens.
-    Bang,
-    BangEqual,
-    Equal,
-    EqualEqual,
-    Greater,
-    GreaterEqual,
-    Less,
-    LessEqual,
-
-    // Literals.
-    Identifier,
-    String,
-    Number,
-
-    // Keywords
-    And,
-    Class,
-    Else,
-    False,
-    Fun,
-    For,
-    If,
-    Nil,
-    Or,
-    Print,
-    Return,
-    Super,
-    This,
-    True,
-    Var,
-    While,
-    Lambda,
-
-    Eof,
-}